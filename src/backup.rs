@@ -0,0 +1,104 @@
+//! Backups of configuration files, taken before each change, for `goto undo`.
+//!
+//! Each backup is a pair of files named after when it was taken: `<time>.path` holds the path of
+//! the configuration file, and `<time>.bak` its previous contents. If the file didn't exist
+//! before, there's no `.bak` file, and undoing the change removes it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{edit, output, state_dir};
+
+/// How many backups are kept. Older ones are removed as new ones are taken.
+const MAX_BACKUPS: usize = 50;
+
+/// Where backups are kept, if there's anywhere suitable.
+fn backup_dir() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("backups"))
+}
+
+/// The names (without extensions) of all the backups, oldest first.
+fn list_backups(dir: &Path) -> io::Result<Vec<String>> {
+    let mut names = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "path" {
+                return None;
+            }
+            Some(path.file_stem()?.to_str()?.to_owned())
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+fn remove_backup(dir: &Path, name: &str) {
+    let _ = fs::remove_file(dir.join(name).with_extension("bak"));
+    let _ = fs::remove_file(dir.join(name).with_extension("path"));
+}
+
+/// Back up a configuration file's contents, or `None` if it doesn't exist yet, before changing
+/// it. Does nothing if there's nowhere to keep backups.
+pub fn save(config_path: &Path, contents: Option<&str>) -> io::Result<()> {
+    let Some(dir) = backup_dir() else {
+        return Ok(());
+    };
+    let config_path = config_path.to_str().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "the path isn't valid Unicode")
+    })?;
+    fs::create_dir_all(&dir)?;
+
+    // Zero-padded, so that sorting the names sorts them by time.
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let name = format!("{:020}", time.as_nanos());
+    if let Some(contents) = contents {
+        fs::write(dir.join(&name).with_extension("bak"), contents)?;
+    }
+    // The path goes last, since its presence is what marks the backup as complete.
+    fs::write(dir.join(&name).with_extension("path"), config_path)?;
+
+    let backups = list_backups(&dir)?;
+    for old in &backups[.. backups.len().saturating_sub(MAX_BACKUPS)] {
+        remove_backup(&dir, old);
+    }
+    Ok(())
+}
+
+/// Undo the most recent change to a configuration file, by restoring the latest backup.
+pub fn undo() -> Result<(), String> {
+    let dir = backup_dir().ok_or_else(|| "no backups are kept on this system".to_owned())?;
+    let backups = match list_backups(&dir) {
+        Ok(backups) => backups,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(format!("failed to read backups in {:?}: {}", dir, e)),
+    };
+    let name = backups.last().ok_or_else(|| "there are no changes to undo".to_owned())?;
+
+    let read_error = |e| format!("failed to read backup {:?} in {:?}: {}", name, dir, e);
+    let config_path = PathBuf::from(
+        fs::read_to_string(dir.join(name).with_extension("path")).map_err(read_error)?);
+    let contents = match fs::read(dir.join(name).with_extension("bak")) {
+        Ok(contents) => Some(contents),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(read_error(e)),
+    };
+
+    let _lock = edit::lock(&config_path)
+        .map_err(|e| format!("failed to lock configuration {:?}: {}", config_path, e))?;
+    let result = match &contents {
+        Some(contents) => edit::write_atomically(&config_path, contents),
+        None => fs::remove_file(&config_path),
+    };
+    result.map_err(|e| format!("failed to restore {:?}: {}", config_path, e))?;
+    crate::daemon::invalidate();
+
+    remove_backup(&dir, name);
+    match contents {
+        Some(_) => output::note(format!("restored {:?} to before its last change", config_path)),
+        None => output::note(format!("removed {:?}, which didn't exist before its last change",
+            config_path)),
+    }
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Copying text to the system clipboard, for --copy.
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Encode bytes as base64, as the OSC 52 escape sequence needs.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0 .. 4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// The command which copies its stdin to the clipboard on this system, if there is one which
+/// should work.
+fn clipboard_command() -> Option<Command> {
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("pbcopy", &[])
+    } else if cfg!(windows) {
+        ("clip", &[])
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        ("wl-copy", &[])
+    } else if env::var_os("DISPLAY").is_some() {
+        ("xclip", &["-selection", "clipboard"])
+    } else {
+        return None;
+    };
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped()).stdout(Stdio::null()).stderr(Stdio::null());
+    Some(command)
+}
+
+/// Copy text with the system's clipboard command.
+fn copy_with_command(mut command: Command, text: &str) -> io::Result<()> {
+    let mut child = command.spawn()?;
+    child.stdin.take().unwrap().write_all(text.as_bytes())?;
+    let status = child.wait()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("it exited with {}", status)))
+    }
+}
+
+/// Copy text to the clipboard: with the system's clipboard command if there is one, and otherwise
+/// by asking the terminal to, with the OSC 52 escape sequence, which works over SSH too, in
+/// terminals which support it.
+pub fn copy(text: &str) -> Result<(), String> {
+    if let Some(command) = clipboard_command() {
+        if copy_with_command(command, text).is_ok() {
+            return Ok(());
+        }
+    }
+    // stdout goes to the shell, but stderr is normally the terminal.
+    let mut stderr = io::stderr();
+    if !stderr.is_terminal() {
+        return Err("no clipboard command worked, and stderr isn't a terminal".to_owned());
+    }
+    write!(stderr, "\x1b]52;c;{}\x07", base64(text.as_bytes()))
+        .map_err(|e| format!("failed to write to the terminal: {}", e))
+}
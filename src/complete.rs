@@ -0,0 +1,111 @@
+//! Shell completion: scripts generated from the command line definition, plus the completion of
+//! shortcut names and the directories under them, which the scripts ask goto for.
+
+use std::path::Path;
+
+use crate::{effective_mapping, lookup, resolve_dest, shell_quote, Configuration, Destination};
+
+/// Complete a directory path below `base`, as a partially typed `partial`, which may include
+/// slashes. Completions end with a slash, so that completion can continue into them.
+fn complete_dir(base: &Path, partial: &str) -> Vec<String> {
+    let (dir, prefix) = match partial.rsplit_once('/') {
+        Some((dir, prefix)) => (format!("{}/", dir), prefix),
+        None => (String::new(), partial),
+    };
+    let Ok(entries) = std::fs::read_dir(base.join(&dir)) else {
+        return vec![];
+    };
+    let mut found = entries.flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name.starts_with(prefix))
+        // Hidden directories only when asked for.
+        .filter(|name| prefix.starts_with('.') || !name.starts_with('.'))
+        .map(|name| format!("{}{}/", dir, name))
+        .collect::<Vec<_>>();
+    found.sort();
+    found
+}
+
+/// Where a shortcut leads on this machine, without running anything to find out.
+fn local_dest(config: &Configuration, cwd: &Path, name: &str) -> Option<std::path::PathBuf> {
+    match resolve_dest(config, cwd, &lookup(config, cwd, name)?.dest).ok()? {
+        Destination::Local(path) => Some(path),
+        _ => None,
+    }
+}
+
+/// The completions of the last of `words`, the arguments typed after the program name so far, as
+/// if they're a shortcut name and extra path components. Options are ignored, and nothing is
+/// completed after a subcommand, since the generated scripts handle those.
+pub fn candidates(config: &Configuration, cwd: &Path, words: &[String], subcommands: &[String])
+    -> Vec<String>
+{
+    let words = words.iter().filter(|word| !word.starts_with('-')).collect::<Vec<_>>();
+    let Some((current, before)) = words.split_last() else {
+        return effective_mapping(config, cwd).into_keys().collect();
+    };
+
+    let Some((name, extra)) = before.split_first() else {
+        // Completing the name itself, or "name/sub/dir".
+        if let Some((name, rest)) = current.split_once('/') {
+            let Some(dest) = local_dest(config, cwd, name) else {
+                return vec![];
+            };
+            return complete_dir(&dest, rest).into_iter()
+                .map(|sub| format!("{}/{}", name, sub))
+                .collect();
+        }
+        return effective_mapping(config, cwd).into_keys()
+            .filter(|name| name.starts_with(current.as_str()))
+            .collect();
+    };
+
+    if subcommands.contains(name) {
+        return vec![];
+    }
+    let (name, rest) = name.split_once('/').unwrap_or((name, ""));
+    let Some(dest) = local_dest(config, cwd, name) else {
+        return vec![];
+    };
+    let mut base = dest.join(rest);
+    for component in extra {
+        base.push(component);
+    }
+    complete_dir(&base, current)
+}
+
+/// The part of a completion script which adds completions of shortcut names and directories, from
+/// running the goto program at `binary`, to those generated from the command line definition.
+/// Not available for all shells.
+pub fn dynamic_script(shell: clap_complete::Shell, name: &str, binary: &str) -> Option<String> {
+    let binary = shell_quote(binary);
+    match shell {
+        clap_complete::Shell::Bash => Some(format!(r#"
+_{name}_shortcuts() {{
+    _{name} "$@"
+    local IFS=$'\n'
+    COMPREPLY+=( $({binary} complete -- "${{COMP_WORDS[@]:1:COMP_CWORD}}" 2>/dev/null) )
+    # Let completion continue into directories.
+    if [[ "${{COMPREPLY[*]}}" == */* ]]; then
+        compopt -o nospace
+    fi
+}}
+complete -F _{name}_shortcuts -o bashdefault -o default {name}
+"#)),
+        clap_complete::Shell::Zsh => Some(format!(r#"
+_{name}_shortcuts() {{
+    _{name} "$@"
+    local -a shortcuts
+    shortcuts=(${{(f)"$({binary} complete -- ${{words[2,CURRENT]}} 2>/dev/null)"}})
+    compadd -S '' -- ${{(M)shortcuts:#*/}}
+    compadd -- ${{shortcuts:#*/}}
+}}
+compdef _{name}_shortcuts {name}
+"#)),
+        clap_complete::Shell::Fish => Some(format!(
+            "\ncomplete -c {name} -f -a \"({binary} complete -- (commandline -opc)[2..-1] \
+                (commandline -ct) 2>/dev/null)\"\n")),
+        _ => None,
+    }
+}
@@ -0,0 +1,452 @@
+//! A resident process which keeps configuration files in memory, already parsed, for when reading
+//! them is slow, as on network filesystems.
+//!
+//! goto asks the daemon for the configuration files that apply in a directory over a unix
+//! socket, and reads them itself if the daemon isn't running. Each request is answered in a thread
+//! of its own, so one waiting on a slow directory doesn't hold up the others.
+//!
+//! The daemon forgets about a directory as soon as inotify reports a change to its configuration
+//! file, on Linux. It also checks the files it holds for changes every `POLL_INTERVAL`, since there
+//! are no notifications elsewhere, or for changes made on other machines to files on a network
+//! filesystem. It drops everything when goto itself edits one.
+//!
+//! Messages are sequences of fields, each written as its length in decimal, a colon, and then its
+//! bytes.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(unix)]
+use std::collections::hash_map::{Entry, HashMap};
+#[cfg(unix)]
+use std::ffi::{CString, OsStr, OsString};
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Read, Write};
+#[cfg(unix)]
+use std::net::Shutdown;
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::sync::{Arc, Mutex, OnceLock};
+#[cfg(unix)]
+use std::time::{Duration, SystemTime};
+
+#[cfg(unix)]
+use serde_json::Value;
+
+#[cfg(unix)]
+use crate::diagnostic::Diagnostic;
+#[cfg(unix)]
+use crate::{
+    in_each_dir, output, read_config, read_config_text, varies_by_run, wire, Configuration,
+    ParsedFile, CONFIG_FILENAMES, DIR_TIMEOUT,
+};
+
+/// The configuration files which apply in a directory, with the result of reading each one, in
+/// increasing order of precedence.
+pub type ConfigFiles = Vec<(PathBuf, io::Result<String>)>;
+
+/// How often the daemon checks whether the files it holds have changed.
+#[cfg(unix)]
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long either side waits on the other before giving up.
+#[cfg(unix)]
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Where the daemon listens: in the user's runtime directory if there is one, otherwise in a
+/// private directory under the temporary directory. Returns None if that directory can't be
+/// created, or isn't private to the user.
+#[cfg(unix)]
+fn socket_path() -> Option<PathBuf> {
+    if let Some(dir) = dirs::runtime_dir() {
+        return Some(dir.join("goto.sock"));
+    }
+
+    let uid = dirs::home_dir()?.metadata().ok()?.uid();
+    let dir = std::env::temp_dir().join(format!("goto-{}", uid));
+    // If this fails, it's probably because it exists already; the checks below make sure it's ours.
+    let _ = fs::DirBuilder::new().mode(0o700).create(&dir);
+    let meta = fs::symlink_metadata(&dir).ok()?;
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return None;
+    }
+    Some(dir.join("daemon.sock"))
+}
+
+#[cfg(unix)]
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+#[cfg(unix)]
+fn write_field(w: &mut impl Write, field: &[u8]) -> io::Result<()> {
+    write!(w, "{}:", field.len())?;
+    w.write_all(field)
+}
+
+#[cfg(unix)]
+fn read_field(r: &mut impl BufRead) -> io::Result<Vec<u8>> {
+    let mut len = vec![];
+    r.read_until(b':', &mut len)?;
+    if len.pop() != Some(b':') {
+        return Err(invalid_data("truncated message"));
+    }
+    let len = std::str::from_utf8(&len).ok()
+        .and_then(|len| len.parse::<usize>().ok())
+        .ok_or_else(|| invalid_data("bad field length"))?;
+    let mut field = vec![0; len];
+    r.read_exact(&mut field)?;
+    Ok(field)
+}
+
+#[cfg(unix)]
+fn read_path_field(r: &mut impl BufRead) -> io::Result<PathBuf> {
+    read_field(r).map(|bytes| PathBuf::from(OsString::from_vec(bytes)))
+}
+
+/// Send a request to the daemon and return its response, or None if it isn't running or doesn't
+/// answer in time. It may have to wait up to `DIR_TIMEOUT` for directories it hasn't loaded yet.
+#[cfg(unix)]
+fn request(fields: &[&[u8]]) -> Option<Vec<u8>> {
+    let mut stream = UnixStream::connect(socket_path()?).ok()?;
+    stream.set_read_timeout(Some(DIR_TIMEOUT + TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(TIMEOUT)).ok()?;
+    for field in fields {
+        write_field(&mut stream, field).ok()?;
+    }
+    stream.shutdown(Shutdown::Write).ok()?;
+    let mut response = vec![];
+    stream.read_to_end(&mut response).ok()?;
+    Some(response)
+}
+
+/// Ask the daemon for the configuration files which apply in `cwd`, as `config_file_paths` would
+/// find them, leaving out ones which don't exist, each parsed. Returns None if the daemon isn't
+/// available.
+#[cfg(unix)]
+pub fn fetch_configs(home_config_path: &Path, cwd: &Path) -> Option<Vec<ParsedFile>> {
+    let home = home_config_path.parent()?;
+    let response = request(&[b"query", cwd.as_os_str().as_bytes(), home.as_os_str().as_bytes()])?;
+
+    let mut reader = &response[..];
+    let count = String::from_utf8(read_field(&mut reader).ok()?).ok()?.parse::<usize>().ok()?;
+    let mut files = vec![];
+    for _ in 0 .. count {
+        let path = read_path_field(&mut reader).ok()?;
+        let status = read_field(&mut reader).ok()?;
+        let body = read_field(&mut reader).ok()?;
+        let json = || serde_json::from_slice::<Value>(&body).ok();
+        let config = match &status[..] {
+            b"config" => Ok(wire::read_config(&json()?)?),
+            b"invalid" => Err(wire::read_diagnostic(&json()?)?),
+            // What these say depends on how goto was run, so they're parsed here.
+            b"text" => read_config(&path, Ok(String::from_utf8(body).ok()?)).transpose()?,
+            _ => read_config(&path, Err(io::Error::other(String::from_utf8(body).ok()?)))
+                .transpose()?,
+        };
+        files.push((path, config));
+    }
+    Some(files)
+}
+
+#[cfg(not(unix))]
+pub fn fetch_configs(_home_config_path: &Path, _cwd: &Path) -> Option<Vec<crate::ParsedFile>> {
+    None
+}
+
+/// Tell the daemon, if it's running, that configuration files have changed.
+#[cfg(unix)]
+pub fn invalidate() {
+    let _ = request(&[b"invalidate"]);
+}
+
+#[cfg(not(unix))]
+pub fn invalidate() {}
+
+/// Enough about a file to tell whether it has changed.
+#[cfg(unix)]
+type Stamp = (u64, Option<SystemTime>, u64);
+
+/// A configuration file, as the daemon holds it.
+#[cfg(unix)]
+#[derive(Clone)]
+enum Held {
+    /// What the file says, or what's wrong with it.
+    Parsed(Box<Result<Configuration, Diagnostic>>),
+    /// The file's text, or why it couldn't be read, for goto to parse itself, since what it says
+    /// depends on how goto is run (see `varies_by_run`).
+    Text(Result<String, String>),
+}
+
+/// What the daemon knows about one directory.
+#[cfg(unix)]
+struct DirConfig {
+    /// Stamps of each of the possible configuration files, in the order of `CONFIG_FILENAMES`.
+    stamps: Vec<Option<Stamp>>,
+
+    /// The configuration file in effect, and what it says.
+    file: Option<(PathBuf, Held)>,
+}
+
+/// What the daemon knows about each directory. Those still being loaded are None.
+#[cfg(unix)]
+type Cache = Mutex<HashMap<PathBuf, Option<DirConfig>>>;
+
+#[cfg(unix)]
+fn stamps(dir: &Path) -> Vec<Option<Stamp>> {
+    CONFIG_FILENAMES.iter()
+        .map(|name| {
+            fs::metadata(dir.join(name)).ok()
+                .map(|meta| (meta.ino(), meta.modified().ok(), meta.len()))
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn load_dir(dir: &Path) -> DirConfig {
+    // Watch and stamp first, so that a change made while reading is noticed later.
+    watch_dir(dir);
+    let stamps = stamps(dir);
+    let file = CONFIG_FILENAMES.iter()
+        .zip(&stamps)
+        .find(|(_, stamp)| stamp.is_some())
+        .map(|(name, _)| {
+            let path = dir.join(name);
+            let held = match read_config_text(&path) {
+                Ok(text) if !varies_by_run(&text) => {
+                    // It was there a moment ago, so it isn't missing.
+                    let config = read_config(&path, Ok(text)).map(Option::unwrap_or_default);
+                    Held::Parsed(Box::new(config))
+                }
+                text => Held::Text(text.map_err(|e| e.to_string())),
+            };
+            (path, held)
+        });
+    DirConfig { stamps, file }
+}
+
+/// The daemon's side of `fetch_configs`.
+///
+/// Directories it doesn't know about yet are loaded with `in_each_dir`, without holding the cache's
+/// lock, since on a network filesystem that can take a long time, or forever. Those that don't
+/// respond in time are left out of the answer, as goto itself would leave them out, and added to
+/// the cache whenever they finish, while they're left out of later answers too.
+#[cfg(unix)]
+fn config_files(cache: &Arc<Cache>, cwd: &Path, home: &Path) -> Vec<(PathBuf, Held)> {
+    let mut dirs = cwd.ancestors().filter(|dir| *dir != home).collect::<Vec<_>>();
+    dirs.reverse();
+    dirs.push(home);
+
+    let mut unknown = vec![];
+    {
+        let mut known = cache.lock().unwrap();
+        for dir in &dirs {
+            if let Entry::Vacant(entry) = known.entry(dir.to_path_buf()) {
+                entry.insert(None);
+                unknown.push(dir.to_path_buf());
+            }
+        }
+    }
+    let loading = Arc::clone(cache);
+    in_each_dir(unknown, move |dir| {
+        let config = load_dir(dir);
+        loading.lock().unwrap().insert(dir.to_owned(), Some(config));
+    });
+
+    let known = cache.lock().unwrap();
+    dirs.into_iter()
+        .filter_map(|dir| known.get(dir)?.as_ref()?.file.clone())
+        .collect()
+}
+
+/// Directories being watched with inotify, by their watch descriptors, along with the inotify
+/// instance's file descriptor.
+#[cfg(target_os = "linux")]
+static WATCHED: OnceLock<(libc::c_int, Mutex<HashMap<libc::c_int, PathBuf>>)> = OnceLock::new();
+
+/// Start watching for changes to configuration files with inotify, and in another thread, forget
+/// about directories whose files change, forever. If inotify isn't available, this does nothing,
+/// and polling still notices the changes.
+#[cfg(target_os = "linux")]
+fn notify(cache: &Arc<Cache>) {
+    // SAFETY: this only creates a file descriptor.
+    let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+    if fd < 0 || WATCHED.set((fd, Mutex::default())).is_err() {
+        return;
+    }
+    let cache = Arc::clone(cache);
+    std::thread::spawn(move || read_notifications(fd, &cache));
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn notify(_cache: &Arc<Cache>) {}
+
+/// Forget about directories as inotify reports changes to their configuration files, forever.
+#[cfg(target_os = "linux")]
+fn read_notifications(fd: libc::c_int, cache: &Cache) {
+    let header = std::mem::size_of::<libc::inotify_event>();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        // SAFETY: the buffer is valid for writes of its length.
+        let len = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        let Ok(len) = usize::try_from(len) else {
+            if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return;
+        };
+        let mut offset = 0;
+        while offset + header <= len {
+            // SAFETY: the kernel writes whole events, each a header followed by `len` bytes of
+            // name, and the header is read unaligned, since the buffer isn't aligned for it.
+            let event = unsafe {
+                std::ptr::read_unaligned(buf[offset ..].as_ptr().cast::<libc::inotify_event>())
+            };
+            let name_len = event.len as usize;
+            let name = buf[offset + header .. offset + header + name_len]
+                .split(|&b| b == 0).next().unwrap_or_default();
+            offset += header + name_len;
+
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                // Some changes weren't reported, so any of them could have changed.
+                cache.lock().unwrap().clear();
+                continue;
+            }
+            let mut watched = WATCHED.get().unwrap().1.lock().unwrap();
+            let gone = event.mask & (libc::IN_DELETE_SELF | libc::IN_MOVE_SELF | libc::IN_IGNORED);
+            if gone != 0 || CONFIG_FILENAMES.iter().any(|file| file.as_bytes() == name) {
+                if let Some(dir) = watched.get(&event.wd) {
+                    cache.lock().unwrap().remove(dir);
+                }
+            }
+            if event.mask & libc::IN_IGNORED != 0 {
+                watched.remove(&event.wd);
+            }
+        }
+    }
+}
+
+/// Have inotify report changes to the configuration files in `dir`, if it's available.
+#[cfg(target_os = "linux")]
+fn watch_dir(dir: &Path) {
+    let (Some((fd, watched)), Ok(c_dir)) = (WATCHED.get(), CString::new(dir.as_os_str().as_bytes()))
+    else {
+        return;
+    };
+    let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_MODIFY | libc::IN_ATTRIB
+        | libc::IN_MOVED_FROM | libc::IN_MOVED_TO | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF;
+    // SAFETY: the path is a valid C string, and `fd` an inotify instance, which is never closed.
+    let wd = unsafe { libc::inotify_add_watch(*fd, c_dir.as_ptr(), mask) };
+    if wd >= 0 {
+        watched.lock().unwrap().insert(wd, dir.to_owned());
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn watch_dir(_dir: &Path) {}
+
+/// Forget about directories whose configuration files have changed, forever.
+#[cfg(unix)]
+fn poll(cache: &Cache) {
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        // Check without holding the lock, since this may be slow.
+        let known = cache.lock().unwrap()
+            .iter()
+            .filter_map(|(dir, config)| Some((dir.clone(), config.as_ref()?.stamps.clone())))
+            .collect::<Vec<_>>();
+        let changed = known.into_iter()
+            .filter(|(dir, old)| stamps(dir) != *old)
+            .map(|(dir, _)| dir)
+            .collect::<Vec<_>>();
+        if !changed.is_empty() {
+            let mut cache = cache.lock().unwrap();
+            for dir in changed {
+                cache.remove(&dir);
+            }
+        }
+    }
+}
+
+/// Answer one request.
+#[cfg(unix)]
+fn serve(mut stream: &UnixStream, cache: &Arc<Cache>) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    match &read_field(&mut reader)?[..] {
+        b"query" => {
+            let cwd = read_path_field(&mut reader)?;
+            let home = read_path_field(&mut reader)?;
+            if !cwd.is_absolute() || !home.is_absolute() {
+                return Err(invalid_data("relative path in query"));
+            }
+            let files = config_files(cache, &cwd, &home);
+            let mut response = vec![];
+            write_field(&mut response, files.len().to_string().as_bytes())?;
+            for (path, held) in files {
+                let (status, body) = match held {
+                    Held::Parsed(parsed) => match *parsed {
+                        Ok(config) => ("config", wire::config(&config).to_string()),
+                        Err(diag) => ("invalid", wire::diagnostic(&diag).to_string()),
+                    },
+                    Held::Text(Ok(text)) => ("text", text),
+                    Held::Text(Err(msg)) => ("unreadable", msg),
+                };
+                write_field(&mut response, path.as_os_str().as_bytes())?;
+                write_field(&mut response, status.as_bytes())?;
+                write_field(&mut response, body.as_bytes())?;
+            }
+            stream.write_all(&response)
+        }
+        b"invalidate" => {
+            cache.lock().unwrap().clear();
+            Ok(())
+        }
+        other => {
+            Err(invalid_data(format!("unknown request {:?}", OsStr::from_bytes(other))))
+        }
+    }
+}
+
+/// Run the daemon until it's killed.
+#[cfg(unix)]
+pub fn run() -> Result<(), String> {
+    let path = socket_path()
+        .ok_or_else(|| "unable to find a private directory for the daemon's socket".to_owned())?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(format!("the daemon is already running, at {:?}", path));
+    }
+    // Left over from a daemon that didn't exit cleanly.
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .map_err(|e| format!("failed to listen at {:?}: {}", path, e))?;
+    output::note(format!("listening at {:?}", path));
+
+    let cache = Arc::new(Cache::default());
+    notify(&cache);
+    let polled = Arc::clone(&cache);
+    std::thread::spawn(move || poll(&polled));
+
+    for stream in listener.incoming() {
+        let cache = Arc::clone(&cache);
+        std::thread::spawn(move || {
+            if let Err(e) = stream.and_then(|stream| serve(&stream, &cache)) {
+                output::warn("daemon", format!("goto daemon: {}", e));
+            }
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn run() -> Result<(), String> {
+    Err("the daemon is only supported on Unix".to_owned())
+}
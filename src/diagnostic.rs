@@ -0,0 +1,82 @@
+//! Descriptions of errors, for reporting either to people or to other programs.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// How serious a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// goto couldn't do what was asked.
+    Error,
+    /// Something went wrong, but goto carried on.
+    Warning,
+    /// Just a note of what goto did.
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A short, stable identifier for the kind of problem, like "config-syntax" or "not-found".
+    pub kind: &'static str,
+    /// The full explanation, as shown to people.
+    pub message: String,
+    /// The configuration file at fault, if any.
+    pub file: Option<PathBuf>,
+    /// The key in the configuration file at fault, if any.
+    pub key: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(kind: &'static str, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, kind, message: message.into(), file: None, key: None }
+    }
+
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = severity;
+        self
+    }
+
+    pub fn with_file(mut self, file: &Path) -> Self {
+        self.file = Some(file.to_owned());
+        self
+    }
+
+    pub fn with_key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = self.file.as_ref().map(|path| path.to_string_lossy());
+        serde_json::json!({
+            "severity": self.severity.to_string(),
+            "kind": self.kind,
+            "file": file,
+            "key": self.key,
+            "message": self.message,
+        }).to_string()
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<String> for Diagnostic {
+    fn from(message: String) -> Self {
+        Self::new("error", message)
+    }
+}
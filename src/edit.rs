@@ -0,0 +1,197 @@
+//! Changing configuration files safely.
+//!
+//! All subcommands that modify configuration files go through `edit_config`, which holds an
+//! advisory lock for the whole read-modify-write cycle, so that two instances of goto can't
+//! interleave their changes, and replaces the file atomically, so that nothing ever reads a
+//! partially written one. TOML files keep their comments and formatting, except where they're
+//! changed. The previous contents are backed up first, for `goto undo`.
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::backup;
+use crate::{parse_config_text, read_config_text, state_dir, ConfigFormat};
+
+/// A path next to `path`, with `suffix` added to its file name.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// An exclusive lock on a configuration file, held until this is dropped.
+///
+/// The lock is taken on a separate file, because the configuration file itself gets replaced
+/// whenever it's written. It's kept in goto's state directory, so that none are left lying around
+/// next to configuration files in people's repositories.
+pub struct ConfigLock {
+    _file: File,
+}
+
+/// The file to lock for the given path: in the `locks` directory in goto's state directory, named
+/// after a hash of the real path, so that every way of naming the file gets the same lock. Without
+/// a state directory, it's next to the file.
+fn lock_path(path: &Path) -> io::Result<PathBuf> {
+    let Some(locks) = state_dir().map(|state| state.join("locks")) else {
+        return Ok(sibling_path(path, ".lock"));
+    };
+    // The file may not exist yet, but its directory has to.
+    let real = match (path.canonicalize(), path.parent(), path.file_name()) {
+        (Ok(real), _, _) => real,
+        (Err(_), Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+            parent.canonicalize()?.join(name)
+        }
+        (Err(e), _, _) => return Err(e),
+    };
+    // FNV-1a, which unlike std's hashers is the same in every build of goto, so that they all
+    // agree on the name.
+    let hash = real.as_os_str().as_encoded_bytes().iter().fold(0xcbf29ce484222325_u64, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x100000001b3)
+    });
+    fs::create_dir_all(&locks)?;
+    Ok(locks.join(format!("{:016x}.lock", hash)))
+}
+
+/// Wait for an exclusive lock on the given configuration file.
+pub fn lock(path: &Path) -> io::Result<ConfigLock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(lock_path(path)?)?;
+    file.lock()?;
+    Ok(ConfigLock { _file: file })
+}
+
+/// Write out a configuration file in the format its name calls for, replacing its contents.
+///
+/// The new contents go to a temporary file first, which then replaces the original. If the path
+/// is a symbolic link, the file it points to is the one replaced.
+pub fn write_config_toml(path: &Path, config_toml: &toml::value::Table) -> io::Result<()> {
+    let text = match ConfigFormat::of(path) {
+        ConfigFormat::Toml => toml::to_string(config_toml).map_err(io::Error::other)?,
+        ConfigFormat::Yaml => serde_yaml::to_string(config_toml).map_err(io::Error::other)?,
+        ConfigFormat::Json => serde_json::to_string_pretty(config_toml).map_err(io::Error::other)?,
+    };
+    write_atomically(path, text.as_bytes())
+}
+
+/// Replace a file's contents by writing a temporary file and renaming it over the original,
+/// keeping the original's permissions.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = match path.canonicalize() {
+        Ok(real) => real,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => path.to_owned(),
+        Err(e) => return Err(e),
+    };
+    let temp_path = sibling_path(&path, ".tmp");
+
+    let result = (|| {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(&path) {
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+        }
+        fs::rename(&temp_path, &path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+    where E: Into<Box<dyn std::error::Error + Send + Sync>>
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Convert a value to the equivalent TOML document item, written inline.
+fn to_item(value: &toml::Value) -> io::Result<toml_edit::Item> {
+    value.to_string()
+        .parse::<toml_edit::Value>()
+        .map(toml_edit::Item::Value)
+        .map_err(invalid_data)
+}
+
+/// Make the changes between `original` and `changed` to a TOML document's table, leaving
+/// everything else in it alone.
+fn apply_changes(
+    table: &mut dyn toml_edit::TableLike,
+    original: &toml::value::Table,
+    changed: &toml::value::Table,
+) -> io::Result<()> {
+    for key in original.keys() {
+        if !changed.contains_key(key) {
+            table.remove(key);
+        }
+    }
+    for (key, value) in changed {
+        let old = original.get(key);
+        if old == Some(value) {
+            continue;
+        }
+        // Change tables key by key, so that what's unchanged in them is kept as it is.
+        if let (Some(toml::Value::Table(old)), toml::Value::Table(new)) = (old, value) {
+            if let Some(sub) = table.get_mut(key).and_then(toml_edit::Item::as_table_like_mut) {
+                apply_changes(sub, old, new)?;
+                continue;
+            }
+        }
+        table.insert(key, to_item(value)?);
+    }
+    Ok(())
+}
+
+/// Write the changes made to a TOML configuration file's contents, by applying them to its
+/// original text, so that comments and formatting are kept.
+fn write_toml_preserving(
+    path: &Path,
+    original_text: &str,
+    original: &toml::value::Table,
+    changed: &toml::value::Table,
+) -> io::Result<()> {
+    let mut doc = original_text.parse::<toml_edit::DocumentMut>().map_err(invalid_data)?;
+    apply_changes(doc.as_table_mut(), original, changed)?;
+    write_atomically(path, doc.to_string().as_bytes())
+}
+
+/// Change a configuration file while holding its lock. It's read (or treated as empty if it
+/// doesn't exist yet), given to `edit`, and written back if `edit` returns true.
+pub fn edit_config<F>(path: &Path, edit: F) -> Result<(), String>
+    where F: FnOnce(&mut toml::value::Table) -> Result<bool, String>
+{
+    let _lock = lock(path)
+        .map_err(|e| format!("failed to lock configuration {:?}: {}", path, e))?;
+
+    let read_error = |e| format!("failed to read configuration {:?}: {}", path, e);
+    let text = match read_config_text(path) {
+        Ok(text) => Some(text),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(read_error(e)),
+    };
+    let original = match &text {
+        Some(text) => parse_config_text(path, text).map_err(read_error)?,
+        None => toml::value::Table::new(),
+    };
+
+    let mut config_toml = original.clone();
+    if edit(&mut config_toml)? {
+        backup::save(path, text.as_deref())
+            .map_err(|e| format!("failed to back up configuration {:?}: {}", path, e))?;
+        let result = match (&text, ConfigFormat::of(path)) {
+            (Some(text), ConfigFormat::Toml) => {
+                write_toml_preserving(path, text, &original, &config_toml)
+            }
+            _ => write_config_toml(path, &config_toml),
+        };
+        result.map_err(|e| format!("failed to write configuration {:?}: {}", path, e))?;
+        crate::daemon::invalidate();
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+//! Approximate string matching, for suggesting names when one isn't found.
+
+/// The Levenshtein edit distance between two strings, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev_row: Vec<usize> = (0 ..= b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev_row[j] + usize::from(ca != *cb);
+            let insertion = row[j] + 1;
+            let deletion = prev_row[j + 1] + 1;
+            row.push(substitution.min(insertion).min(deletion));
+        }
+        prev_row = row;
+    }
+    prev_row[b.len()]
+}
+
+/// Whether `candidate` is similar enough to `wanted` to be worth suggesting in its place: it
+/// differs only in case, one contains the other, or it's within a few edits of it.
+pub fn is_similar(wanted: &str, candidate: &str) -> bool {
+    let wanted = wanted.to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if wanted.contains(&candidate) || candidate.contains(&wanted) {
+        return true;
+    }
+    let allowed = (wanted.chars().count() / 3).max(1);
+    edit_distance(&wanted, &candidate) <= allowed
+}
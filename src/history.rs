@@ -0,0 +1,237 @@
+//! Records of where the user has been, kept in goto's state directory.
+//!
+//! Visits to directories, which the shell hooks from `goto init --track` report, are appended to
+//! the `visits` file, one per line, as the time in seconds since the Unix epoch, a tab, and the
+//! path.
+//!
+//! Jumps goto makes are appended to the `jumps` file the same way, with the time, the name of the
+//! shortcut used (empty if there wasn't one), the destination, and the directory goto was run
+//! in, separated by tabs.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{NaiveDate, TimeZone};
+
+use crate::state_dir;
+
+/// A jump recorded in the history.
+pub struct Jump {
+    /// When it happened, in seconds since the Unix epoch.
+    pub time: u64,
+    pub name: String,
+    pub dest: String,
+    pub cwd: String,
+}
+
+/// The current time, in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Append a record with the current time and the given fields to one of the history files. Does
+/// nothing if there's nowhere to keep it, or a field can't be written without breaking the record.
+fn append(file_name: &str, fields: &[&str]) -> io::Result<()> {
+    let Some(state) = state_dir() else {
+        return Ok(());
+    };
+    if fields.iter().any(|field| field.contains(['\n', '\t'])) {
+        return Ok(());
+    }
+    fs::create_dir_all(&state)?;
+    // Appending one short line at a time keeps lines from concurrent shells whole.
+    let line = format!("{}\t{}\n", now(), fields.join("\t"));
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(state.join(file_name))?
+        .write_all(line.as_bytes())
+}
+
+/// Record a visit to a directory.
+pub fn record_visit(dir: &Path) -> io::Result<()> {
+    match dir.to_str() {
+        Some(dir) => append("visits", &[dir]),
+        None => Ok(()),
+    }
+}
+
+/// Record a jump goto made, to `dest`, with the shortcut `name` if one was used, from `cwd`.
+pub fn record_jump(name: Option<&str>, dest: &str, cwd: &Path) -> io::Result<()> {
+    match cwd.to_str() {
+        Some(cwd) => append("jumps", &[name.unwrap_or(""), dest, cwd]),
+        None => Ok(()),
+    }
+}
+
+/// Read the records in one of the history files which have `field_count` fields after the time,
+/// oldest first, as the time and the fields. Lines which can't be understood are skipped.
+fn read(file_name: &str, field_count: usize) -> io::Result<Vec<(u64, Vec<String>)>> {
+    let Some(state) = state_dir() else {
+        return Ok(vec![]);
+    };
+    let file = match File::open(state.join(file_name)) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut records = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let Some((time, rest)) = line.split_once('\t') else {
+            continue;
+        };
+        let fields = rest.split('\t').map(str::to_owned).collect::<Vec<_>>();
+        if let (Ok(time), true) = (time.parse::<u64>(), fields.len() == field_count) {
+            records.push((time, fields));
+        }
+    }
+    Ok(records)
+}
+
+/// Read the recorded jumps made at or after `since`, in seconds since the Unix epoch, oldest
+/// first.
+pub fn jumps(since: u64) -> io::Result<Vec<Jump>> {
+    Ok(read("jumps", 3)?
+        .into_iter()
+        .filter(|(time, _)| *time >= since)
+        .map(|(time, fields)| {
+            let [name, dest, cwd] = <[String; 3]>::try_from(fields).unwrap();
+            Jump { time, name, dest, cwd }
+        })
+        .collect())
+}
+
+/// What's known about the places in the history: when each was last jumped to or visited, and
+/// when the oldest record is from.
+struct Summary {
+    last_visits: BTreeMap<PathBuf, u64>,
+    oldest: Option<u64>,
+}
+
+/// Read the history files, only the first time they're needed in a run, since they only ever grow.
+fn summary() -> &'static Summary {
+    static SUMMARY: OnceLock<Summary> = OnceLock::new();
+    SUMMARY.get_or_init(|| {
+        let jumps = read("jumps", 3).unwrap_or_default()
+            .into_iter()
+            .map(|(time, mut fields)| (time, fields.swap_remove(1)));
+        let visits = read("visits", 1).unwrap_or_default()
+            .into_iter()
+            .map(|(time, mut fields)| (time, fields.swap_remove(0)));
+        let mut summary = Summary { last_visits: BTreeMap::new(), oldest: None };
+        for (time, path) in jumps.chain(visits) {
+            let entry = summary.last_visits.entry(PathBuf::from(path)).or_insert(time);
+            *entry = time.max(*entry);
+            summary.oldest = Some(summary.oldest.map_or(time, |oldest| oldest.min(time)));
+        }
+        summary
+    })
+}
+
+/// The last time each place was jumped to or visited, in seconds since the Unix epoch. If the
+/// history can't be read, it's empty.
+pub fn last_visits() -> &'static BTreeMap<PathBuf, u64> {
+    &summary().last_visits
+}
+
+/// The time of the oldest record in the history, if there are any.
+fn oldest_record() -> Option<u64> {
+    summary().oldest
+}
+
+/// Tells which directories have gone unused for a while: neither they nor anything below them has
+/// been jumped to or visited, and they haven't been modified either.
+pub struct Staleness {
+    last_visits: &'static BTreeMap<PathBuf, u64>,
+    /// Anything last used before this, in seconds since the Unix epoch, is stale.
+    cutoff: u64,
+}
+
+impl Staleness {
+    /// Tell which directories have gone unused for `after` seconds. If the history doesn't go back
+    /// that far, as when it isn't recorded, there's no telling, so there's nothing.
+    pub fn new(after: u64) -> Option<Staleness> {
+        let cutoff = now().saturating_sub(after);
+        if oldest_record()? > cutoff {
+            return None;
+        }
+        Some(Staleness { last_visits: last_visits(), cutoff })
+    }
+
+    /// If `dir` is stale, when it was last used, if it was at all as far as the history goes.
+    /// Directories which don't exist aren't stale: they're missing.
+    pub fn last_used(&self, dir: &Path) -> Option<Option<u64>> {
+        let modified = fs::metadata(dir).ok()?.modified().ok()?;
+        let modified = modified.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_secs());
+        if modified >= self.cutoff {
+            return None;
+        }
+        match last_visit_within(self.last_visits, dir) {
+            Some(time) if time >= self.cutoff => None,
+            last => Some(last),
+        }
+    }
+}
+
+/// The last time `dir`, or anything below it, was jumped to or visited, from `last_visits`.
+pub fn last_visit_within(last_visits: &BTreeMap<PathBuf, u64>, dir: &Path) -> Option<u64> {
+    // Paths below `dir` sort right after it.
+    last_visits.range(dir.to_owned() ..)
+        .take_while(|(path, _)| path.starts_with(dir))
+        .map(|(_, time)| *time)
+        .max()
+}
+
+/// The directory within `dir` (or `dir` itself) most recently jumped to or visited, if it still
+/// exists. If the history can't be read, there isn't one.
+pub fn last_visited_under(dir: &Path) -> Option<PathBuf> {
+    // Paths below `dir` sort right after it.
+    last_visits().range(dir.to_owned() ..)
+        .take_while(|(path, _)| path.starts_with(dir))
+        .filter(|(path, _)| path.is_dir())
+        .max_by_key(|(_, time)| **time)
+        .map(|(path, _)| path.clone())
+}
+
+/// Parse the argument to `goto history --since`: a date, as YYYY-MM-DD, meaning the start of
+/// that day, or a number of minutes, hours, days, or weeks ago, like "30m", "12h", "7d", or "2w".
+/// Returns the time in seconds since the Unix epoch.
+pub fn parse_since(since: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid time {:?}: expected YYYY-MM-DD, or a number followed by \
+        m, h, d, or w", since);
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0)
+            .and_then(|midnight| chrono::Local.from_local_datetime(&midnight).earliest())
+            .map(|time| u64::try_from(time.timestamp()).unwrap_or(0))
+            .ok_or_else(invalid);
+    }
+    let ago = parse_duration(since).ok_or_else(invalid)?;
+    Ok(now().saturating_sub(ago))
+}
+
+/// Parse a number of minutes, hours, days, or weeks, like "30m", "12h", "7d", or "2w", as a number
+/// of seconds.
+pub fn parse_duration(duration: &str) -> Option<u64> {
+    let unit = match duration.chars().last()? {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let count = duration[.. duration.len() - 1].parse::<u64>().ok()?;
+    Some(count.saturating_mul(unit))
+}
+
+/// Format a time, in seconds since the Unix epoch, as a local date and time.
+pub fn format_time(time: u64) -> String {
+    i64::try_from(time).ok()
+        .and_then(|time| chrono::Local.timestamp_opt(time, 0).single())
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| time.to_string())
+}
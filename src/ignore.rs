@@ -0,0 +1,112 @@
+//! `.gotoignore` files, which keep directories out of `goto scan` and searches under search roots.
+//!
+//! They're written like `.gitignore` files: one pattern per line, with blank lines and lines
+//! starting with '#' skipped. A pattern applies to the directory the file is in and everything
+//! below it. '*' and '?' match within a name, and '**' matches any number of directories. A
+//! pattern with a slash in it, other than at the end, matches paths relative to the file's
+//! directory; otherwise it matches names at any depth. A pattern starting with '!' brings back
+//! what earlier ones ignored, and the last pattern to match wins. Only directories are looked at,
+//! so a trailing slash makes no difference.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::scan::glob_match;
+
+const IGNORE_FILENAME: &str = ".gotoignore";
+
+struct Rule {
+    /// The directory the rule's file is in.
+    base: PathBuf,
+    negated: bool,
+    /// Whether it matches paths relative to `base`, rather than names at any depth.
+    anchored: bool,
+    segments: Vec<String>,
+}
+
+impl Rule {
+    fn parse(line: &str, base: &Path) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, pattern) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = pattern.contains('/');
+        let segments = pattern.trim_start_matches('/')
+            .split('/')
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        if segments.iter().all(String::is_empty) {
+            return None;
+        }
+        Some(Rule { base: base.to_owned(), negated, anchored, segments })
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let names = relative.iter().map(|name| name.to_string_lossy()).collect::<Vec<_>>();
+        let names = names.iter().map(|name| name.as_ref()).collect::<Vec<_>>();
+        if self.anchored {
+            match_segments(&self.segments, &names)
+        } else {
+            names.last().is_some_and(|name| glob_match(&self.segments[0], name))
+        }
+    }
+}
+
+/// Match path components against pattern segments, where "**" matches any number of components.
+fn match_segments(segments: &[String], names: &[&str]) -> bool {
+    match segments.split_first() {
+        None => names.is_empty(),
+        Some((segment, rest)) if segment == "**" => {
+            (0 ..= names.len()).any(|skip| match_segments(rest, &names[skip ..]))
+        }
+        Some((segment, rest)) => match names.split_first() {
+            Some((name, names)) => glob_match(segment, name) && match_segments(rest, names),
+            None => false,
+        },
+    }
+}
+
+/// The rules from the `.gotoignore` files which apply somewhere.
+#[derive(Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// The rules which apply in `dir`: those from its ignore file, and those of all its parents.
+    /// Files which can't be read are skipped.
+    pub fn for_dir(dir: &Path) -> Ignore {
+        let mut ignore = Ignore::default();
+        let mut ancestors = dir.ancestors().collect::<Vec<_>>();
+        ancestors.reverse();
+        for ancestor in ancestors {
+            ignore.add_dir(ancestor);
+        }
+        ignore
+    }
+
+    /// Add the rules from `dir`'s ignore file, if it has one, to apply below it. Rules only apply
+    /// below their own directory, so those from anywhere can be added, so long as each directory's
+    /// are added after its parents'.
+    pub fn add_dir(&mut self, dir: &Path) {
+        if let Ok(text) = fs::read_to_string(dir.join(IGNORE_FILENAME)) {
+            self.rules.extend(text.lines().filter_map(|line| Rule::parse(line, dir)));
+        }
+    }
+
+    /// Whether the directory at `path` is ignored. Its parents aren't checked.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.rules.iter()
+            .rev()
+            .find(|rule| rule.matches(path))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
@@ -0,0 +1,152 @@
+//! Formatting of the shortcut listing.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::history::{self, Staleness};
+use crate::{missing_paths, output, Destination, PathMappingEntry};
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const CYAN: &str = "\x1b[36m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Formats entries for `--list`, which is written to stderr.
+pub struct ListFormatter {
+    color: bool,
+    staleness: Option<Staleness>,
+    missing: Option<BTreeSet<PathBuf>>,
+}
+
+impl ListFormatter {
+    /// Use color if stderr is a terminal, unless the NO_COLOR environment variable is set.
+    pub fn new() -> Self {
+        Self { color: output::use_color(), staleness: None, missing: None }
+    }
+
+    /// Mark entries whose destinations are stale, as `staleness` tells.
+    pub fn with_staleness(mut self, staleness: Option<Staleness>) -> Self {
+        self.staleness = staleness;
+        self
+    }
+
+    /// Look for the local destinations of `entries` all at once, rather than one at a time as
+    /// each is formatted.
+    pub fn checking<'a>(mut self, entries: impl Iterator<Item = &'a PathMappingEntry>) -> Self {
+        let paths = entries
+            .filter_map(|entry| match &entry.dest {
+                Destination::Local(path) => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+        self.missing = Some(missing_paths(paths));
+        self
+    }
+
+    fn is_missing(&self, entry: &PathMappingEntry) -> bool {
+        let Destination::Local(path) = &entry.dest else {
+            return false;
+        };
+        match &self.missing {
+            Some(missing) => missing.contains(path),
+            None => !missing_paths(vec![path.clone()]).is_empty(),
+        }
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_owned()
+        }
+    }
+
+    /// Format one line of the listing. Entries whose destination doesn't exist are dimmed, and
+    /// those whose destination is stale are marked as unused. Destinations which don't respond
+    /// aren't dimmed, since it isn't known whether they exist.
+    pub fn format_entry(&self, name: &str, entry: &PathMappingEntry) -> String {
+        let missing = self.is_missing(entry);
+        let dest_style = if missing { DIM } else { "" };
+
+        let mut line = format!("{} → {} (from {}",
+            self.paint(BOLD, name),
+            self.paint(dest_style, &entry.dest.to_string()),
+            self.paint(CYAN, &format!("{:?}", entry.source_file)));
+        if let Some(context) = &entry.context {
+            line += &format!(", context {}", self.paint(YELLOW, &format!("{:?}", context)));
+        }
+        line.push(')');
+        if let (Some(staleness), Destination::Local(path)) = (&self.staleness, &entry.dest) {
+            let mark = match staleness.last_used(path) {
+                None => None,
+                Some(None) => Some(" [unused]".to_owned()),
+                Some(Some(time)) => Some(format!(" [unused since {}]", history::format_time(time))),
+            };
+            if let Some(mark) = mark {
+                line += &self.paint(YELLOW, &mark);
+            }
+        }
+        if missing && self.color {
+            // Each painted part ends by resetting all styles, dimness included, so dim again after.
+            line = format!("{}{}{}", DIM, line.replace(RESET, &format!("{}{}", RESET, DIM)), RESET);
+        }
+        line
+    }
+
+    /// Format the listing of entries sorted by the parts of their names as a tree: dotted names
+    /// like "work.api" are shown as "api", indented under a "work" heading.
+    pub fn format_tree(&self, entries: &[(String, PathMappingEntry)]) -> Vec<String> {
+        let mut lines = vec![];
+        let mut prev: Vec<&str> = vec![];
+        for (name, entry) in entries {
+            let parts = name.split('.').collect::<Vec<_>>();
+            let (namespaces, leaf) = parts.split_at(parts.len() - 1);
+            let common = prev.iter().zip(namespaces).take_while(|(a, b)| a == b).count();
+            for (depth, namespace) in namespaces.iter().enumerate().skip(common) {
+                lines.push(format!("{}{}", "  ".repeat(depth), self.paint(BOLD, namespace)));
+            }
+            lines.push(format!("{}{}", "  ".repeat(namespaces.len()),
+                self.format_entry(leaf[0], entry)));
+            prev = parts;
+        }
+        lines
+    }
+}
+
+/// The fields of a record in the --list output for other programs: the name, destination, source
+/// file, and tags, which are joined with commas.
+pub fn record_fields(name: &str, entry: &PathMappingEntry) -> [String; 4] {
+    [
+        name.to_owned(),
+        entry.dest.to_string(),
+        entry.source_file.display().to_string(),
+        entry.tags.join(","),
+    ]
+}
+
+/// Format a line of tab-separated values. Tabs, newlines, and backslashes in fields are escaped
+/// with backslashes, so they don't break up the record.
+pub fn tsv_record(fields: &[String]) -> String {
+    fields.iter()
+        .map(|field| {
+            field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+                .replace('\r', "\\r")
+        })
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+/// Format a line of comma-separated values, quoting the fields which need it (RFC 4180).
+pub fn csv_record(fields: &[String]) -> String {
+    fields.iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -3,20 +3,93 @@
 //! Copyright (c) 2016-2024 by William R. Fraser
 
 use std::collections::btree_map::*;
+use std::collections::BTreeSet;
 use std::env;
+use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{self, Read, Write};
-use std::path::{Path, PathBuf};
-use clap::Parser;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use chrono::Datelike;
+use clap::{CommandFactory, Parser};
+use unicode_normalization::UnicodeNormalization;
+use diagnostic::{Diagnostic, Severity};
+use output::{exit, fail, report};
 
+mod backup;
+mod clipboard;
+mod complete;
+mod daemon;
+mod diagnostic;
+mod edit;
+mod fuzzy;
+mod history;
+mod ignore;
+mod list;
+mod migrate;
+mod output;
+mod scan;
+mod search;
+mod session;
+mod shell;
+mod temp;
+mod tidy;
+mod timing;
+#[cfg(unix)]
+mod wire;
+
+/// The name of new configuration files.
 const CONFIG_FILENAME: &str = ".goto.toml";
 
+/// The names configuration files can have, in order of priority when a directory has more than
+/// one: only the first one found is used.
+const CONFIG_FILENAMES: &[&str] = &[CONFIG_FILENAME, ".goto.yaml", ".goto.yml", ".goto.json"];
+
+/// The formats configuration files can be written in. They all share the same structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl ConfigFormat {
+    /// Determine a configuration file's format from its extension.
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => ConfigFormat::Yaml,
+            Some("json") => ConfigFormat::Json,
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// Where goto keeps what it records for itself, like backups and history, if there's anywhere
+/// suitable.
+fn state_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("goto"))
+}
+
+/// Find the configuration file in a directory. If there isn't one, this is the path a new one
+/// should be created at.
+fn find_config_file(dir: &Path) -> PathBuf {
+    CONFIG_FILENAMES.iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dir.join(CONFIG_FILENAME))
+}
+
 //  79 columns:
 //  ----------------------------------------------------------------------------
 
 /// goto :: Flexible Working Directory Shortcuts
 ///
-/// Configuration is stored in ~/.goto.toml, with the following format:
+/// Configuration is stored in ~/.goto.toml, with the following format (YAML
+/// or JSON with the same structure can be used instead, in ~/.goto.yaml or
+/// ~/.goto.json):
 ///
 ///     name = "/some/path"             # 'goto name' takes you here
 ///     othername = "~/some/other/path" # $HOME expansion will happen
@@ -30,222 +103,4208 @@ const CONFIG_FILENAME: &str = ".goto.toml";
 /// /somewhere/specific, running 'goto name' takes you to
 /// /somewhere/specific/somewhere/else.
 ///
+/// Contexts can be nested, for a subdirectory of the enclosing context's path:
+///
+///     ["/somewhere/specific".deeper]  # For /somewhere/specific/deeper:
+///     "name" = "elsewhere"            # Overrides the one above.
+///
+/// Where several contexts apply, shortcuts in the more specific one override
+/// those in the others, and the others' shortcuts remain available.
+///
 /// Configuration files can also be placed in any directory and will affect any
 /// invocations of goto from that directory or below it. In the case of
 /// conflicts, configurations from farther down the tree take precedence, and
-/// the one in your home directory takes precedence over all others.
+/// the one in your home directory takes precedence over all others, unless it
+/// sets 'home-lowest = true' in its [settings] table, which lets the others
+/// override it instead.
+///
+/// A configuration file can name overlays, more configuration files to read
+/// at the lowest precedence, like one shared by a team and committed to their
+/// repository, with 'overlays = ["goto.shared.toml"]' in its [settings] table.
+/// $GOTO_OVERLAYS can also list their absolute paths, separated like $PATH.
+/// goto's subcommands never change overlays, unless they're named explicitly.
+///
+/// Destinations can also be on another machine, reached over SSH:
+///
+///     buildbox = "ssh://user@build01:/srv/builds"
+///
+/// For these, instead of a local directory change, goto prints a command that
+/// logs in to the remote host and starts a shell in the given directory.
+///
+/// Similarly, a table with a "container" key starts a shell in a directory
+/// inside a running container:
+///
+///     app = { container = "myapp", path = "/srv/app", shell = "bash" }
+///
+/// With --git, the .goto.toml at the root of the enclosing git repository is
+/// treated as a context for the whole repository: its top-level shortcuts
+/// apply anywhere in the repository, and take precedence over those in your
+/// home directory. The name 'root' also takes you to the repository root,
+/// unless a configuration defines it otherwise.
+///
+/// Without --git, 'root' takes you to the nearest directory, here or above,
+/// containing .git, .hg, Cargo.toml, or package.json. Set 'root-markers' in
+/// the [settings] table to a list of other names to look for.
+///
+/// If <extra> is provided as extra arguments, they are appended to the computed
+/// path as path components, so 'goto proj src tests' takes you to the
+/// 'src/tests' subdirectory of 'proj'. They can also be given along with the
+/// name, separated by a slash: 'goto proj/src/tests' is the same thing. An
+/// absolute path there is an error, unless --absolute-extra says otherwise.
+///
+/// If the destination is a file, goto takes you to the directory containing it,
+/// and with --open, also opens the file in your editor.
+///
+/// If the resulting directory doesn't exist, goto reports the closest existing
+/// directory and any similarly named ones instead; use --no-verify, or set
+/// 'verify-extra = false' in the [settings] table, to skip this check.
+///
+/// If a name isn't defined by any configuration, but is the path of an
+/// existing directory, goto takes you there instead. This can be disabled with
+/// --no-path-fallback, or in a configuration file with:
+///
+///     [settings]
+///     path-fallback = false
+///
+/// Directories can also be found without defining shortcuts for them, by
+/// listing shortcuts to search under:
+///
+///     [settings]
+///     search-roots = ["src"]
+///
+/// Then a name which isn't defined is looked for one or two levels below the
+/// 'src' shortcut's destination.
+///
+/// Where it's found in more than one place, the best is gone to, or with -i,
+/// you're asked which. Places one level down are better than two, and recently
+/// visited ones better still; a root can also be given a weight, relative to
+/// the default of 1:
+///
+///     search-roots = ["src", { root = "archive", weight = 0.2 }]
+///
+/// With --export, or 'export-vars = true' in the [settings] table, goto also
+/// sets GOTO_NAME and GOTO_LAST in your shell, to the name of the shortcut used
+/// and the directory it took you to, for use in prompts and hooks.
+///
+/// Similarly, --title or 'terminal-title = true' sets the terminal's title to
+/// the name of the shortcut; --no-title overrides the setting.
+///
+/// A shortcut can list several alternative destinations, of which the first
+/// one that exists is used, for sharing configuration between machines:
+///
+///     scratch = ["/mnt/fast/scratch", "~/scratch"]
+///
+/// Shortcuts can be written as tables, to give them a description and tags:
+///
+///     version = 2
+///     proj = { path = "~/src/proj", desc = "The project", tags = ["work"] }
+///
+/// With 'newest-child = true' in the table, the shortcut leads to the most
+/// recently modified subdirectory of its path, such as the latest build:
+///
+///     latest = { path = "~/builds", newest-child = true }
+///
+/// In a context, 'last-visited = true' makes a shortcut lead to the directory
+/// in the context goto last took you to (or that 'init --track' recorded you
+/// visiting), falling back to its path, or the context's own directory:
+///
+///     ["~/src/proj"]
+///     "*" = { last-visited = true, path = "src" }
+///
+/// In ~/.goto.toml only, a shortcut can lead to the directory printed by a
+/// shell command, which is run (in the configuration file's directory, or the
+/// context's) each time the shortcut is used:
+///
+///     top = { cmd = "git rev-parse --show-toplevel" }
+///
+/// Also in ~/.goto.toml only, a shortcut to a directory which has to be
+/// mounted first can give the command which mounts it. If the directory doesn't
+/// exist or is empty, the command is run before going there:
+///
+///     nas = { path = "/mnt/nas/media", mount = "sshfs nas:/media /mnt/nas/media" }
+///
+/// Only use commands you trust; other configuration files can't define them.
+///
+/// Paths can include today's date, with the placeholders {yyyy}, {yy}, {mm},
+/// and {dd}; add --create to make the directory if it doesn't exist yet, or
+/// write the shortcut as a table with 'create = true' to always do so:
+///
+///     journal = { path = "~/notes/{yyyy}/{mm}", create = true }
+///
+/// A shortcut written as a table with 'confirm = true', like one leading to a
+/// production server's files, asks on the terminal before going there, unless
+/// --yes is given:
+///
+///     prod = { path = "/mnt/prod", confirm = true }
+///
+/// Similarly, {branch} is the git branch checked out in the current directory:
+///
+///     ci = "~/ci-artifacts/{branch}"
+///
+/// Setting a shortcut to false hides it, which is useful in a context to hide
+/// a shortcut defined elsewhere while you're in that directory:
+///
+///     ["/work/customer"]
+///     tmp = false
+///
+/// A shortcut can lead wherever another one does, by naming it after an '@':
+///
+///     work = "@proj"
+///
+/// A context can change the command used to change to its shortcuts' directories
+/// from the one given with --cmd, and so can a shortcut written as a table:
+///
+///     ["~/src/proj"]
+///     cd-cmd = "cd"
+///     docs = { path = "docs", cd-cmd = "pushd" }
+///
+/// A table can also give the shortcut a 'priority' (the default is 0), which
+/// overrides the usual precedence between configuration files: when several
+/// files define the same name, the highest priority wins.
+///
+/// A shortcut with 'pinned = true' in its table is left alone by commands that
+/// change many shortcuts at once, like 'goto prune', 'goto rename-path' and
+/// 'goto add-children --force', unless they're given --include-pinned.
+///
+/// At the top level this needs 'version = 2', because in older files such a
+/// table is a context. 'goto migrate' upgrades configuration files.
+///
+/// Profiles switch between sets of shortcuts, for example between work and
+/// home. With --profile=work, or GOTO_PROFILE=work in the environment, the
+/// contents of the [profile.work] table are used as well as the rest of the
+/// file, replacing same-named shortcuts:
 ///
-/// If <extra> is provided as an extra argument, it is appended to the computed
-/// path.
+///     [profile.work]
+///     docs = "/mnt/share/docs"
+///
+/// Tables under [hosts] override shortcuts, contexts, and settings on just one
+/// machine, named by its host name (in full, or the part before the first
+/// dot), so that one file can be shared between machines where paths differ:
+///
+///     [hosts.laptop]
+///     docs = "/Users/me/docs"
+///
+/// They win over all the configuration files, and keep the description, tags
+/// and other details of the shortcuts they override, unless they give their own.
+///
+/// When nothing matches, goto says it's not sure where to go. To go somewhere
+/// instead, set 'fallback = "~"' (or any other destination) in the
+/// [settings] table; goto then exits with status 3, to tell scripts that it
+/// fell back. 'fallback = "error"' makes it exit with status 1 instead.
+///
+/// Shortcuts can be grouped by giving them dotted names, which have to be
+/// quoted, like '"work.api" = "~/src/api"'. Then 'goto work' asks which of the
+/// shortcuts named 'work.<something>' to go to, and --list shows them under a
+/// 'work' heading.
+///
+/// Shortcut names are case-sensitive, unless 'name-case = "insensitive"' is
+/// set in the [settings] table. With 'name-case = "smart"', they are
+/// case-insensitive only when the name you type is all lowercase.
+///
+/// Names and context paths match even when they're written in different
+/// Unicode normalization forms (macOS gives 'café' in decomposed form, for
+/// example). Set 'normalize-unicode = false' to compare them exactly.
+///
+/// Contexts apply where their path is a prefix of the current directory. Set
+/// 'resolve-contexts = true' to also apply them where that's true after
+/// resolving symbolic links in both.
+///
+/// To jump to a shortcut with the same name as one of the subcommands below,
+/// put '--' before it, as in 'goto -- scan'.
 ///
 /// goto is meant to be used as the argument to your shell's 'eval' builtin,
-/// like:
-///     function goto() {
-///         eval $(/usr/local/bin/goto $*)  # or wherever the 'goto' binary is
-///     }
+/// through a shell function which 'goto init' prints. For example, in
+/// ~/.bashrc:
+///     eval "$(/usr/local/bin/goto init bash)"  # or wherever 'goto' is
+///
+/// Run on its own, with its output going to the terminal, goto can't change
+/// the shell's directory, so it says how to set that up. With
+/// 'unwrapped = "subshell"' in the [settings] table, it starts a new shell in
+/// the destination directory instead.
 #[derive(Parser, Debug)]
-#[clap(version, verbatim_doc_comment)]
+#[clap(version, verbatim_doc_comment, args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true)]
 struct Args {
-    /// The command to output to change directory.
+    /// The command to output to change directory, where the shortcut or its
+    /// context doesn't give one with 'cd-cmd'. The quoted path is added to the
+    /// end, or with '{}' in the command, put there instead, like
+    /// --cmd='tmux new-window -c {}'.
     #[arg(short, long="cmd", default_value="pushd")]
     command: String,
 
+    /// The shell the output is for. Windows paths, like UNC paths to network
+    /// shares, need special handling in PowerShell and cmd.exe.
+    #[arg(long, value_enum, default_value_t = shell::Shell::Sh)]
+    shell: shell::Shell,
+
+    /// The command used to log in to remote (ssh://) destinations. It is
+    /// followed by the host and the command to run there.
+    #[arg(long="ssh-cmd", default_value="ssh -t")]
+    ssh_command: String,
+
+    /// The command used to enter container destinations. It is followed by
+    /// the working directory option, the container name, and the shell.
+    #[arg(long="container-cmd", default_value="docker exec -it")]
+    container_command: String,
+
     /// List the currently available shortcuts.
     #[arg(short, long)]
     list: bool,
 
+    /// Only list shortcuts specific to the current directory: those from
+    /// contexts that match it, and from configuration files other than
+    /// ~/.goto.toml.
+    #[arg(long, requires = "list")]
+    local: bool,
+
+    /// When the name is defined in more than one place, ask which definition
+    /// to use, instead of using the one that takes precedence.
+    #[arg(short = 'i', long)]
+    choose: bool,
+
+    /// List every definition of the name in effect here, from all contexts
+    /// and configuration files, in order of precedence, instead of going to
+    /// the first one. If there are none, it exits with a non-zero status.
+    #[arg(long, conflicts_with = "list")]
+    all: bool,
+
+    /// How to order the --list output.
+    #[arg(long, value_enum, default_value_t = ListSort::Name, requires = "list")]
+    sort: ListSort,
+
+    /// Report on stderr how long each phase of starting up took: finding and
+    /// reading each configuration file, parsing it, merging them all, and
+    /// resolving where to go.
+    #[arg(long)]
+    profile_startup: bool,
+
+    /// Don't print any messages; failure to find where to go is indicated only
+    /// by a non-zero exit status.
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// How much of each message to show: only the first line with 'terse', or
+    /// with 'verbose', also what kind of message each is, and the file and key
+    /// at fault. A [settings.messages] table can reword messages by kind, as in
+    /// 'not-found = "goto: {message}"'; '{kind}', '{file}', and '{key}' are
+    /// filled in too.
+    #[arg(long, value_enum, default_value_t = output::MessageStyle::Normal)]
+    message_style: output::MessageStyle,
+
+    /// The format of messages. With 'json', each message is written to stderr
+    /// as an object with "severity", "kind", "file", "key", and "message" fields,
+    /// and instead of a command to go to the destination, an object describing
+    /// it is written to stdout, with "name", "kind", "dest", "dir", "file",
+    /// "extra", "source", "context", and "command" fields, for editor plugins.
+    /// With 'tsv' or 'csv', --list writes a record for each shortcut to stdout
+    /// instead, with its name, destination, source file, and tags.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Use the shortcuts, contexts, and settings in the [profile.<PROFILE>]
+    /// tables of configuration files, in addition to the rest. Defaults to
+    /// $GOTO_PROFILE.
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Treat the configuration at the root of the enclosing git repository as
+    /// a context for the whole repository, with the 'root' shortcut leading to
+    /// the repository root.
+    #[arg(long)]
+    git: bool,
+
+    /// Don't treat names which aren't defined as shortcuts as paths to go to.
+    #[arg(long)]
+    no_path_fallback: bool,
+
+    /// Don't check that the path with <extra> appended exists.
+    #[arg(long)]
+    no_verify: bool,
+
+    /// Create the directory (with <extra> appended) if it doesn't exist.
+    #[arg(long)]
+    create: bool,
+
+    /// Don't ask before going to shortcuts marked 'confirm = true'.
+    #[arg(long)]
+    yes: bool,
+
+    /// Copy the path to the clipboard, instead of changing to it. Where there's
+    /// no clipboard command to use, the terminal is asked to, which works over
+    /// SSH too, if it supports that (with the OSC 52 escape sequence).
+    #[arg(long)]
+    copy: bool,
+
+    /// Print the path, instead of a command to change to it, for scripts.
+    #[arg(long, conflicts_with_all = ["copy", "cd_file"])]
+    print_path: bool,
+
+    /// End each path printed by --print-path or --batch with a NUL character
+    /// instead of a newline, for 'xargs -0' and the like, so paths with
+    /// newlines in them come through intact.
+    #[arg(short = '0', long)]
+    print0: bool,
+
+    /// Write the directory to this file, instead of printing a command to
+    /// change to it, for file managers which change to the directory named in
+    /// a file after running a command, like lf, ranger, and nnn.
+    #[arg(long, value_name = "PATH", conflicts_with = "copy")]
+    cd_file: Option<PathBuf>,
+
+    /// How to write the directory to the --cd-file: as just the path, which lf
+    /// (-last-dir-path), ranger (--choosedir), and yazi (--cwd-file) read, or
+    /// as a 'cd' command, which nnn reads (from $NNN_TMPFILE).
+    #[arg(long, value_enum, default_value_t = CdFileFormat::Path, requires = "cd_file")]
+    cd_file_format: CdFileFormat,
+
+    /// When the destination is a file, also open it in $EDITOR.
+    #[arg(long)]
+    open: bool,
+
+    /// Output the path relative to the current directory, when that's shorter.
+    #[arg(long)]
+    relative: bool,
+
+    /// Resolve symbolic links in the destination, and output the real path.
+    #[arg(short = 'P', long, conflicts_with = "logical")]
+    physical: bool,
+
+    /// Output the destination as configured, without resolving symbolic
+    /// links. This is the default, unless 'resolve-symlinks = true' is set.
+    #[arg(short = 'L', long)]
+    logical: bool,
+
+    /// Find configuration files and match contexts as if goto were run in
+    /// this directory, instead of the current one.
+    #[arg(long, value_name = "PATH")]
+    cwd: Option<PathBuf>,
+
+    /// Match contexts against the current directory with symbolic links
+    /// resolved, instead of the path the shell reports in $PWD.
+    #[arg(long)]
+    physical_cwd: bool,
+
+    /// Also set GOTO_NAME and GOTO_LAST in the shell, to the name of the
+    /// shortcut used and the directory it led to.
+    #[arg(long)]
+    export: bool,
+
+    /// Also set the terminal's title to the name of the shortcut used.
+    #[arg(long, conflicts_with = "no_title")]
+    title: bool,
+
+    /// Tell the terminal the directory changed to, so it can open new tabs
+    /// there, in terminals known to support that (with the OSC 7 escape
+    /// sequence). Also enabled by 'report-cwd = true' in the [settings] table.
+    #[arg(long)]
+    report_cwd: bool,
+
+    /// Don't set the terminal's title, even if configured to.
+    #[arg(long)]
+    no_title: bool,
+
+    /// Read shortcut names from stdin, one per line, and print the paths they
+    /// lead to, one per line, for scripts. A line can also be a directory, a
+    /// tab, and a name, to resolve the name as seen from that directory. Names
+    /// which can't be resolved get an empty line, and an error on stderr.
+    #[arg(long, conflicts_with_all = ["list", "name", "all"])]
+    batch: bool,
+
+    /// Go to each of the names given, instead of one name with <extra> after
+    /// it: inside tmux, each in a pane of its own, and otherwise, by printing
+    /// the command to go to each one on a line of its own, for scripts.
+    /// --export, --title, and --report-cwd, which are about one place gone
+    /// to, can't be given with it.
+    #[arg(long, conflicts_with_all = ["list", "all", "choose", "copy", "cd_file", "create",
+        "export", "title", "report_cwd"])]
+    each: bool,
+
+    /// Where --each goes to each name: a tmux pane or window of its own, or
+    /// just a line of output.
+    #[arg(long, value_enum, requires = "each")]
+    each_in: Option<EachIn>,
+
     /// Name of the shortcut to change directory to.
-    #[arg(
-        default_value_if("list", "true", Some("")),
-        required(false),
-        required_unless_present("list"),
-    )]
-    name: String,
+    #[arg(required_unless_present_any(["list", "batch"]))]
+    name: Option<String>,
+
+    /// Optional subpath to be appended to the shortcut's path. Multiple
+    /// arguments are joined as path components.
+    extra: Vec<String>,
 
-    /// Optional subpath to be appended to the shortcut's path.
-    extra: Option<String>,
+    /// What to do when <extra> is an absolute path, which appending to the
+    /// shortcut's path would replace it with.
+    #[arg(long, value_enum, default_value_t = AbsoluteExtra::Reject)]
+    absolute_extra: AbsoluteExtra,
+
+    #[command(subcommand)]
+    action: Option<Action>,
 }
 
-fn read_config_toml(config_path: &Path) -> io::Result<toml::value::Table> {
-    let mut config_text = String::new();
-    let mut file = File::open(config_path)?;
-    file.read_to_string(&mut config_text)?;
-    match toml::from_str(&config_text) {
-        Ok(config) => Ok(config),
-        Err(e) => {
-            Err(io::Error::new(io::ErrorKind::Other, format!("failed to parse TOML: {}", e)))
-        }
-    }
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Tsv,
+    Csv,
 }
 
-type PathMapping = BTreeMap<String, PathMappingEntry>;
+/// How to write the directory to the --cd-file.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CdFileFormat {
+    /// Just the path.
+    Path,
+    /// A 'cd' command, as nnn expects.
+    Nnn,
+}
 
-#[derive(Debug, Clone)]
-struct PathMappingEntry {
-    dest: PathBuf,
-    source_file: PathBuf,
+/// Where `--each` goes to each name.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum EachIn {
+    /// A new pane in the current tmux window, which is the default inside tmux.
+    Panes,
+    /// A new tmux window, named after the shortcut.
+    Windows,
+    /// A line of output with the command to go there, or with --print-path,
+    /// the path. This is the default outside tmux.
+    Lines,
 }
 
-#[derive(Debug, Default)]
-struct Configuration {
-    global: PathMapping,
-    contexts: BTreeMap<PathBuf, PathMapping>,
+/// What to do with an <extra> which is an absolute path.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum AbsoluteExtra {
+    /// Refuse to go anywhere.
+    Reject,
+    /// Go to the absolute path, instead of under the shortcut's destination.
+    Replace,
+    /// Append it to the shortcut's destination anyway, as if it were relative.
+    Relative,
 }
 
-/// Make the given TOML value into an absolute path. It should be a string, otherwise an error is
-/// returned. If the path is relative, it is made absolute by interpreting it relative to the given
-/// path, or to the user's home directory if it starts with "~/".
-fn parse_toml_as_path(t: &toml::Value, relative_to: &Path) -> Result<PathBuf, String> {
-    if let toml::Value::String(ref s) = *t {
-        let path: PathBuf = if s.starts_with("~/") || s.starts_with("~\\") {
-            dirs::home_dir().unwrap().join(Path::new(&s[2..]))
-        } else {
-            // note: this handles absolute paths correctly, by not using `relative_to` at all
-            // (except for Windows, where the drive letter of `relative_to` may be considered).
-            relative_to.join(Path::new(&s))
-        };
-        Ok(path)
-    } else {
-        Err(format!("type error: expected a string, not {}", t.type_str()))
-    }
+/// What `goto export` can print.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ExportKind {
+    /// A value for CDPATH, so 'cd name' works for directories under the
+    /// search roots, or else next to the shortcuts' destinations.
+    Cdpath,
 }
 
-/// Process the parsed configuration TOML into goto's configuration struct.
-/// All relative paths will be interpreted relative to `relative_to`.
-fn process_config(config_file_path: &Path, config_toml: toml::value::Table, relative_to: &Path)
-    -> Result<Configuration, String>
-{
-    let mut config = Configuration::default();
+/// Orderings for the --list output.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ListSort {
+    /// By shortcut name.
+    Name,
+    /// By destination, which groups related shortcuts together.
+    Path,
+    /// By the configuration file that defines the shortcut.
+    Source,
+    /// By when the shortcut's destination, or anything below it, was last
+    /// gone to or visited, most recently first. Those never used come last.
+    Recency,
+}
 
-    for (k, v) in config_toml {
-        if let toml::Value::Table(t) = v {
-            // A path context.
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// Find git and hg repositories under a directory, and add a shortcut to
+    /// each one, named after its directory.
+    ///
+    /// Directories ruled out by .gotoignore files, which are written like
+    /// .gitignore files, in the directory or above or below it, are skipped.
+    /// They apply to searches under search roots too.
+    Scan {
+        /// The directory to search.
+        dir: PathBuf,
 
-            let context_path = match parse_toml_as_path(&toml::Value::String(k), relative_to) {
-                Ok(path) => path,
-                Err(msg) => { return Err(format!("error: {}", msg)); }
-            };
+        /// How many levels of subdirectories to search.
+        #[arg(long, default_value_t = 3)]
+        depth: usize,
 
-            let mut context_map = PathMapping::new();
+        /// Skip directories with names matching this pattern ('*' and '?'
+        /// wildcards are supported). Can be given more than once.
+        #[arg(long, value_name = "PATTERN")]
+        ignore: Vec<String>,
 
-            for (name, path) in t {
-                let mapped_path: PathBuf = match parse_toml_as_path(&path, &context_path) {
-                    Ok(path) => path,
-                    Err(msg) => {
-                        return Err(format!("error at {:?}.{}: {}", context_path, name, msg));
-                    }
-                };
+        /// The configuration file to add shortcuts to, instead of
+        /// ~/.goto.toml.
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+    },
 
-                context_map.insert(name, PathMappingEntry {
-                    source_file: config_file_path.to_owned(),
-                    dest: mapped_path,
-                });
-            }
+    /// Add a shortcut for each immediate subdirectory of a directory, named
+    /// after the subdirectory.
+    AddChildren {
+        /// The directory whose subdirectories to add.
+        dir: PathBuf,
 
-            config.contexts.insert(context_path, context_map);
-        } else {
-            // A top-level entry. Attempt to parse as a path and insert into the global table.
-            let mapped_path: PathBuf = match parse_toml_as_path(&v, relative_to) {
-                Ok(path) => path,
-                Err(msg) => {
-                    return Err(format!(
-                        "error at {}: expected a table or a path string, not {} ({})",
-                         k, v.type_str(), msg));
-                },
-            };
+        /// A prefix to put on each shortcut's name.
+        #[arg(long, default_value = "")]
+        prefix: String,
 
-            config.global.insert(k, PathMappingEntry {
-                source_file: config_file_path.to_owned(),
-                dest: mapped_path,
-            });
-        }
+        /// Replace shortcuts which already exist with the same name, instead of
+        /// skipping them.
+        #[arg(long)]
+        force: bool,
+
+        /// With --force, replace pinned shortcuts too.
+        #[arg(long, requires = "force")]
+        include_pinned: bool,
+
+        /// The configuration file to add shortcuts to, instead of
+        /// ~/.goto.toml.
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+    },
+
+    /// Show which shortcuts lead to a directory (or one above it), and what to
+    /// type to get back there.
+    NameOf {
+        /// The directory to look up. Defaults to the current directory.
+        path: Option<PathBuf>,
+    },
+
+    /// List the shortcuts in effect here whose names, destinations, tags, or
+    /// descriptions contain every word of the query, best matches first.
+    Search {
+        /// The words to search for.
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        /// Ask which of the matches to go to, and go there.
+        #[arg(short = 'i', long)]
+        choose: bool,
+    },
+
+    /// Open a shortcut's directory in the system's file manager, instead of
+    /// changing to it.
+    Reveal {
+        /// The name of the shortcut.
+        name: String,
+
+        /// A subpath to append to the shortcut's path, as with goto itself.
+        extra: Vec<String>,
+    },
+
+    /// Open each of a workspace's shortcuts in a tmux window of its own.
+    ///
+    /// Workspaces are defined in configuration files, as lists of shortcuts:
+    ///
+    ///     [workspace.morning]
+    ///     shortcuts = ["api", "web", "docs/notes"]
+    ///
+    /// Inside tmux, the windows are added to the current session. Otherwise
+    /// they make a new session named after the workspace (or if there already
+    /// is one, it's reused), which goto then attaches to.
+    Workspace {
+        /// The name of the workspace.
+        name: String,
+    },
+
+    /// Print the most specific context that applies in the current directory,
+    /// followed by a tab and the configuration file(s) defining it. Prints
+    /// nothing if no context applies.
+    ///
+    /// This writes to stdout, for use in shell prompts, so run the goto
+    /// program directly rather than through the shell function.
+    Context,
+
+    /// Print the current directory as the closest shortcut leading to it,
+    /// followed by the rest of the path, like "proj/src/tests". Prints nothing
+    /// if no shortcut leads here.
+    ///
+    /// Like 'context', this writes to stdout, for use in shell prompts.
+    Breadcrumb,
+
+    /// Make a symbolic link to each shortcut in effect here which leads to a
+    /// local directory, named after it, in the given directory, for programs
+    /// which can't use goto, like file dialogs. Run it again to bring them up
+    /// to date.
+    Linkfarm {
+        /// The directory to put the links in.
+        dir: PathBuf,
+
+        /// Also remove links in the directory which aren't for shortcuts.
+        #[arg(long)]
+        clean: bool,
+    },
+
+    /// Print a shell command exporting an environment variable for each
+    /// shortcut in effect here which leads to a local directory, like
+    /// "export GOTO_PROJ='/home/me/src/proj'", for scripts and Makefiles. The
+    /// names are upper-cased, with anything else not allowed in a variable
+    /// name replaced by '_'.
+    Env {
+        /// The prefix for the variable names.
+        #[arg(default_value = "GOTO_")]
+        prefix: String,
+    },
+
+    /// Print the configuration in a form other programs understand.
+    ///
+    /// 'goto export cdpath' prints the destinations of the search roots, if
+    /// there are any, or otherwise the parent directories of all the shortcuts
+    /// in effect here which lead to local directories, separated by ':', so
+    /// the shell's 'cd' can find them too:
+    ///
+    ///     export CDPATH=".:$(command goto export cdpath)"
+    Export {
+        what: ExportKind,
+    },
+
+    /// Add a shortcut to a directory. If the name is defined in other
+    /// configuration files in effect here, this asks first.
+    Add {
+        /// The name of the shortcut.
+        name: String,
+
+        /// The directory it leads to. Defaults to the current directory.
+        path: Option<PathBuf>,
+
+        /// Add the shortcut without asking, replacing any existing one with the
+        /// same name in the configuration file.
+        #[arg(long)]
+        force: bool,
+
+        /// The configuration file to add the shortcut to, instead of
+        /// ~/.goto.toml.
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+
+        /// Add a temporary shortcut, which isn't written to a configuration
+        /// file, and lasts until this shell session ends, or with --ttl, for a
+        /// while. It takes precedence over the configuration files.
+        #[arg(long, conflicts_with = "config")]
+        temp: bool,
+
+        /// How long the temporary shortcut lasts, in minutes, hours, days, or
+        /// weeks, like "30m", "8h", "2d", or "1w".
+        #[arg(long, requires = "temp")]
+        ttl: Option<String>,
+    },
+
+    /// Add a shortcut to the current directory, named after it unless a name
+    /// is given.
+    Save {
+        /// The name of the shortcut. Defaults to the current directory's name.
+        name: Option<String>,
+
+        /// Add the shortcut without asking, replacing any existing one with the
+        /// same name in the configuration file.
+        #[arg(long)]
+        force: bool,
+
+        /// The configuration file to add the shortcut to, instead of
+        /// ~/.goto.toml.
+        #[arg(long, value_name = "PATH")]
+        config: Option<PathBuf>,
+    },
+
+    /// Create a configuration file in the current directory, with commented
+    /// examples of what can go in it.
+    NewConfig {
+        /// Create ~/.goto.toml instead.
+        #[arg(long)]
+        home: bool,
+
+        /// Add a shortcut for each immediate subdirectory of the directory the
+        /// file is in, instead of the examples of shortcuts.
+        #[arg(long)]
+        seed: bool,
+    },
+
+    /// Undo the most recent change goto made to a configuration file, by
+    /// restoring the backup taken before it. Repeat to undo earlier changes.
+    Undo,
+
+    /// Print a completion script for the given shell, which completes
+    /// subcommands, options, shortcut names, and directories under shortcuts.
+    /// For PowerShell and Elvish, only subcommands and options are completed.
+    Completions {
+        /// The shell to complete in.
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+
+        /// The name of the shell function goto is used through.
+        #[arg(long, default_value = "goto")]
+        name: String,
+    },
+
+    /// Print the completions of the last word of a goto command line, which
+    /// is given without the program name. Used by completion scripts.
+    #[command(hide = true)]
+    Complete {
+        #[arg(allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+
+    /// List the configuration files in effect in the current directory, from
+    /// lowest to highest precedence, with how many shortcuts each defines.
+    WhichConfig,
+
+    /// Rewrite a TOML configuration file neatly: with keys sorted, contexts in
+    /// order, consistent quoting, and paths in the home directory starting
+    /// with ~/. Comments are kept. Shortcuts which lead to the same place are
+    /// pointed out, but left alone. A file which defines a name twice isn't
+    /// changed; the name and the line of its second definition are reported.
+    Tidy {
+        /// The file to tidy. Defaults to ~/.goto.toml.
+        file: Option<PathBuf>,
+
+        /// Only report whether the file needs tidying, without changing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Update shortcuts and contexts after moving a directory: paths in
+    /// configuration files which lead to it, or anywhere under it, are
+    /// rewritten to lead to the same place under its new location.
+    RenamePath {
+        /// Where the directory was.
+        old: PathBuf,
+
+        /// Where it is now.
+        new: PathBuf,
+
+        /// The files to update. Defaults to all the configuration files in
+        /// effect in the current directory.
+        files: Vec<PathBuf>,
+
+        /// Change pinned shortcuts too.
+        #[arg(long)]
+        include_pinned: bool,
+
+        /// Only show what would change, without changing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// List the names defined in more than one place in effect here, with
+    /// each definition, the one which takes precedence marked with '*'. For
+    /// the others, if they're in configuration files in effect here, this
+    /// offers to delete or rename them.
+    Conflicts {
+        /// Only list them, without offering to change anything.
+        #[arg(long)]
+        list: bool,
+    },
+
+    /// Upgrade configuration files to the newest format version.
+    Migrate {
+        /// The files to upgrade. Defaults to all the configuration files in
+        /// effect in the current directory.
+        files: Vec<PathBuf>,
+
+        /// Only report which files need upgrading, without changing them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Keep configuration files in memory, parsed, and answer goto's requests
+    /// for them, for when reading them is slow, as on network filesystems.
+    /// goto reads the files itself whenever the daemon isn't running.
+    ///
+    /// This runs until killed, so run the goto program directly, in the
+    /// background, rather than through the shell function.
+    Daemon,
+
+    /// Remove shortcuts whose destination directories don't exist anymore,
+    /// asking about each one.
+    Prune {
+        /// The files to remove shortcuts from. Defaults to all the
+        /// configuration files in effect in the current directory.
+        files: Vec<PathBuf>,
+
+        /// Remove them without asking.
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Remove pinned shortcuts too.
+        #[arg(long)]
+        include_pinned: bool,
+    },
+
+    /// Check the shortcuts in effect here for ones worth removing: those whose
+    /// destination directories don't exist, and those which are stale, meaning
+    /// their directories haven't been gone to, visited, or modified in the
+    /// time the 'stale-after' setting gives (26 weeks by default).
+    Doctor,
+
+    /// Print the shell function goto is used through, to be evaluated in
+    /// your shell's startup script. It runs this goto program, at the path it
+    /// was run from.
+    Init {
+        /// The shell to define the function for.
+        #[arg(value_enum)]
+        shell: shell::Shell,
+
+        /// The name of the function.
+        #[arg(long, default_value = "goto")]
+        name: String,
+
+        /// Also add a hook which records each directory the shell changes to,
+        /// with 'goto visit'. Supported in bash, zsh, and fish.
+        #[arg(long)]
+        track: bool,
+    },
+
+    /// List the jumps goto has made, oldest first: when, with which shortcut,
+    /// where to, and from where. Set 'record-history = false' in the
+    /// [settings] table to stop recording them.
+    History {
+        /// Only list jumps since this date (YYYY-MM-DD), or this long ago, as a
+        /// number followed by m, h, d, or w for minutes, hours, days, or weeks.
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+
+        /// The format to list them in. Other than 'text', which is for
+        /// reading, they're written to stdout, one per line.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Record a visit to a directory. Used by the hook 'init --track' adds.
+    #[command(hide = true)]
+    Visit {
+        dir: PathBuf,
+    },
+}
+
+/// Read a configuration file's text.
+fn read_config_text(config_path: &Path) -> io::Result<String> {
+    let mut config_text = String::new();
+    File::open(config_path)?.read_to_string(&mut config_text)?;
+    Ok(config_text)
+}
+
+/// Parse the text of a configuration file, according to the format its name calls for, without
+/// any interpretation of its contents. Files in formats other than TOML are converted to the
+/// equivalent TOML values.
+fn parse_config_text(config_path: &Path, config_text: &str) -> io::Result<toml::value::Table> {
+    let parsed = match ConfigFormat::of(config_path) {
+        ConfigFormat::Toml => toml::from_str(config_text)
+            .map_err(|e| format!("failed to parse TOML: {}", e)),
+        ConfigFormat::Yaml => serde_yaml::from_str(config_text)
+            .map_err(|e| format!("failed to parse YAML: {}", e)),
+        ConfigFormat::Json => serde_json::from_str(config_text)
+            .map_err(|e| format!("failed to parse JSON: {}", e)),
+    };
+    parsed.map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// Format a path for writing into a configuration file, abbreviating the home directory as "~/".
+pub(crate) fn config_path_string(path: &Path, home: &Path) -> String {
+    match path.strip_prefix(home) {
+        Ok(rel) if !rel.as_os_str().is_empty() => format!("~/{}", rel.display()),
+        _ => path.display().to_string(),
     }
+}
 
-    Ok(config)
+/// Whether a shortcut's definition is marked `pinned = true`, which keeps commands that change
+/// many shortcuts at once from changing it, unless they're told to.
+fn is_pinned(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::Table(table) => {
+            matches!(table.get("pinned"), Some(toml::Value::Boolean(true)))
+        }
+        _ => false,
+    }
 }
 
-/// Combine two configurations. The entries in `overlay` take precedence.
-fn combine_configs(combined: &mut Configuration, mut overlay: Configuration) {
-    combined.global.append(&mut overlay.global);
-    for (context_path, mut context) in overlay.contexts {
-        match combined.contexts.entry(context_path) {
-            Entry::Occupied(mut combined_context) => {
-                combined_context.get_mut().append(&mut context);
-            },
-            Entry::Vacant(entry) => {
-                entry.insert(context);
+/// Add new top-level shortcuts to the contents of a configuration file. Names which are already
+/// defined in it are left alone, with a warning, unless `overwrite` is set, and if they're pinned,
+/// `overwrite_pinned` too. Returns how many were added.
+fn insert_entries(
+    config_toml: &mut toml::value::Table,
+    entries: &[(String, PathBuf)],
+    overwrite: bool,
+    overwrite_pinned: bool,
+    home: &Path,
+) -> usize {
+    let mut added = 0;
+    for (name, path) in entries {
+        if let Some(existing) = config_toml.get(name) {
+            if !overwrite {
+                output::warn("skipped",
+                    format!("skipping {}: already defined as {}", name, existing));
+                continue;
             }
+            if is_pinned(existing) && !overwrite_pinned {
+                output::warn("skipped", format!("skipping {}: it's pinned", name));
+                continue;
+            }
+            output::note(format!("replacing {} (was {})", name, existing));
         }
+        let value = config_path_string(path, home);
+        output::note(format!("{} → {}", name, value));
+        config_toml.insert(name.clone(), toml::Value::String(value));
+        added += 1;
     }
+    added
 }
 
-/// Read the configuration file at the given path.
-/// If the file does not exist, returns Ok(None), otherwise if the file cannot be read or processed
-/// for any reason, returns a message explaining the error.
-fn read_config(config_path: &Path) -> Result<Option<Configuration>, String> {
-    let config_toml = match read_config_toml(config_path) {
-        Ok(toml) => toml,
-        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
-        Err(e) => return Err(format!("failed to read configuration {:?}: {}", config_path, e)),
-    };
+/// Add new top-level shortcuts to a configuration file, creating it if necessary. See
+/// `insert_entries`.
+fn add_config_entries(
+    config_path: &Path,
+    entries: &[(String, PathBuf)],
+    overwrite: bool,
+    overwrite_pinned: bool,
+    home: &Path,
+) -> Result<(), String> {
+    edit::edit_config(config_path, |config_toml| {
+        let added = insert_entries(config_toml, entries, overwrite, overwrite_pinned, home);
+        output::note(format!("added {} shortcut(s) to {:?}", added, config_path));
+        Ok(added > 0)
+    })
+}
 
-    process_config(config_path, config_toml, config_path.parent().unwrap())
-        .map_err(|msg| {
-            format!("invalid configuration in {:?}: {}", config_path, msg)
-        })
-        .map(Some)
+/// Ask a yes-or-no question on stderr, and read the answer from stdin. Anything but yes is no.
+fn confirm(question: &str) -> bool {
+    matches!(prompt(&format!("{} [y/N]", question)).as_str(), "y" | "Y" | "yes")
 }
 
-/// Read and combine all configuration files for a given path, by walking up the directory stack
-/// from the root to `cwd`, and finally the user's home configuration. If reading any of them
-/// fails (other than because the file does not exist), returns an appropriate error message.
-fn read_combine_configs(home_config_path: &Path, cwd: &Path) -> Result<Configuration, String> {
-    assert!(cwd.is_absolute());
+/// Ask the user something on stderr, and return their answer, trimmed. It's empty if stdin can't
+/// be read.
+fn prompt(question: &str) -> String {
+    output::ask(question);
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return String::new();
+    }
+    answer.trim().to_owned()
+}
 
-    let mut combined = Configuration::default();
+/// Ask a yes-or-no question on the terminal itself, for when stdin and stderr might be redirected,
+/// and stdout is certainly going to the shell. Anything but yes is no.
+fn confirm_on_terminal(question: &str) -> io::Result<bool> {
+    let (input, output) = if cfg!(windows) {
+        ("CONIN$", "CONOUT$")
+    } else {
+        ("/dev/tty", "/dev/tty")
+    };
+    let mut terminal = std::fs::OpenOptions::new().write(true).open(output)?;
+    write!(terminal, "{} [y/N] ", question)?;
+    let mut answer = String::new();
+    io::BufReader::new(File::open(input)?).read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
 
-    // Accumulate paths by stripping off components until we hit the root.
-    let mut search_paths = Vec::<&Path>::new();
-    let mut maybe_path = Some(cwd);
-    while let Some(path) = maybe_path {
-        search_paths.push(path);
-        maybe_path = path.parent();
-    }
+/// The name `goto save` gives a shortcut to `dir` by default: the directory's own name.
+fn default_shortcut_name(dir: &Path) -> Result<String, String> {
+    dir.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_owned)
+        .ok_or_else(|| format!("can't name a shortcut after {:?}; give a name", dir))
+}
 
-    // Walk from the root up to `cwd`, reading and combining configs if they exist.
-    for path in search_paths.iter().rev() {
-        let toml_path = path.join(CONFIG_FILENAME);
-        if let Some(config) = read_config(&toml_path)? {
-            combine_configs(&mut combined, config);
+/// Add a shortcut to `dest` in the given configuration file. Unless `force` is set, this asks
+/// before replacing an existing one in the same file, or adding one with the same name as any of
+/// the definitions in `elsewhere`, which the new one would shadow or be shadowed by.
+fn add_shortcut(
+    name: &str,
+    dest: &Path,
+    force: bool,
+    config_path: &Path,
+    home: &Path,
+    elsewhere: &[PathMappingEntry],
+) -> Result<(), String> {
+    if !elsewhere.is_empty() && !force {
+        let formatter = list::ListFormatter::new();
+        let mut msg = format!("{} is also defined in other configuration files:", name);
+        for entry in elsewhere {
+            msg += &format!("\n    {}", formatter.format_entry(name, entry));
+        }
+        output::warn("defined-elsewhere", msg);
+        if !confirm("Add it anyway?") {
+            return Err("not added".to_owned());
         }
     }
 
-    if let Some(config) = read_config(home_config_path)? {
-        combine_configs(&mut combined, config);
-    }
-
-    Ok(combined)
+    edit::edit_config(config_path, |config_toml| {
+        let overwrite = match config_toml.get(name) {
+            Some(existing) if !force => {
+                if !confirm(&format!("{} is already defined as {}. Replace it?", name, existing)) {
+                    return Err("not added".to_owned());
+                }
+                true
+            }
+            _ => force,
+        };
+        // Replacing a pinned shortcut by name is explicit enough.
+        let entries = [(name.to_owned(), dest.to_owned())];
+        Ok(insert_entries(config_toml, &entries, overwrite, true, home) > 0)
+    })
 }
 
-fn exit(msg: &str, fatal: bool) -> ! {
-    io::stderr().write_all(msg.as_bytes()).unwrap();
-    if !msg.ends_with('\n') {
-        io::stderr().write_all(b"\n").unwrap();
+/// Upgrade the given configuration files to the current format version.
+fn migrate_files(files: &[PathBuf], dry_run: bool) -> Result<(), String> {
+    for path in files {
+        if !path.is_file() {
+            return Err(format!("{:?} is not a file", path));
+        }
+        edit::edit_config(path, |config_toml| {
+            let changed = migrate::migrate(config_toml)
+                .map_err(|msg| format!("can't upgrade {:?}: {}", path, msg))?;
+            if !changed {
+                output::note(format!("{:?} is up to date", path));
+            } else if dry_run {
+                output::note(format!("{:?} needs upgrading to version {}", path, CONFIG_VERSION));
+            } else {
+                output::note(format!("upgraded {:?} to version {}", path, CONFIG_VERSION));
+            }
+            Ok(changed && !dry_run)
+        })?;
     }
-    let exit_code = if fatal { 1 } else { 0 };
-    ::std::process::exit(exit_code);
+    Ok(())
 }
 
-fn print_path(path: &Path, shellcmd: &str, extra: &str) {
-    if !shellcmd.is_empty() {
-        print!("{} ", shellcmd);
+/// Print each of the given configuration files, with how many shortcuts it defines.
+fn which_config(files: &[PathBuf]) {
+    for path in files {
+        match read_config(path, read_config_text(path)) {
+            Ok(Some(config)) => {
+                let in_contexts = config.contexts.values().map(PathMapping::len).sum::<usize>();
+                output::note(format!("{:?}: {} shortcut(s), {} of them in contexts",
+                    path, config.global.len() + in_contexts, in_contexts));
+            }
+            Ok(None) => (),
+            Err(diag) => report(&diag),
+        }
+    }
+}
+
+/// Find the shortcuts in a configuration file which lead to local directories that don't exist,
+/// as (context key, name) pairs. Ones which are created on demand, or have placeholders in their
+/// paths, are left out, since their directories may well exist later.
+fn missing_entries(config_path: &Path, config_toml: &toml::value::Table)
+    -> Result<Vec<(Option<String>, String)>, String>
+{
+    let dir = config_path.parent().unwrap();
+    let config = process_config(config_path, config_toml.clone(), dir)
+        .map_err(|diag| diag.message)?;
+    let is_missing = |value: &toml::Value, entry: Option<&PathMappingEntry>| {
+        match entry {
+            Some(PathMappingEntry {
+                dest: Destination::Local(path), create: false, mount: None, ..
+            }) => {
+                !path.exists() && !value.to_string().contains('{')
+            }
+            _ => false,
+        }
+    };
+
+    let version = config_version(config_toml);
+    let mut missing = vec![];
+    for (k, v) in config_toml {
+        match v {
+            toml::Value::Table(t) if !is_entry_table(t, version) => {
+                if k == SETTINGS_KEY || k == WORKSPACES_KEY {
+                    continue;
+                }
+                let context_key = toml::Value::String(k.clone());
+                let Some(mapping) = parse_toml_as_path(&context_key, dir).ok()
+                    .and_then(|path| config.contexts.get(&path))
+                else {
+                    continue;
+                };
+                for (name, value) in t {
+                    if is_missing(value, mapping.get(name)) {
+                        missing.push((Some(k.clone()), name.clone()));
+                    }
+                }
+            }
+            v => {
+                if is_missing(v, config.global.get(k)) {
+                    missing.push((None, k.clone()));
+                }
+            }
+        }
+    }
+    Ok(missing)
+}
+
+/// Report the shortcuts in effect in `cwd` which are candidates for removing: those whose
+/// destinations don't exist, and those whose destinations are stale.
+fn doctor(config: &Configuration, cwd: &Path) {
+    let stale_after = config.settings.stale_after.unwrap_or(DEFAULT_STALE_AFTER);
+    let staleness = history::Staleness::new(stale_after);
+    let (mut missing, mut stale) = (0, 0);
+    for (name, entry) in effective_mapping(config, cwd) {
+        // Aliases are checked as the shortcuts they refer to.
+        if let Destination::Alias(_) = entry.dest {
+            continue;
+        }
+        let Ok(Destination::Local(path)) = resolve_dest(config, cwd, &entry.dest) else {
+            continue;
+        };
+        if !path.exists() && !entry.create && entry.mount.is_none() {
+            output::warn("missing-path", format!("{} leads to {:?}, which doesn't exist \
+                (from {:?})", name, path, entry.source_file));
+            missing += 1;
+        } else if let Some(last) = staleness.as_ref().and_then(|s| s.last_used(&path)) {
+            let used = match last {
+                Some(time) => format!("hasn't been used since {}", history::format_time(time)),
+                None => "hasn't been used".to_owned(),
+            };
+            output::warn("stale", format!("{} {}, and {:?} hasn't been modified recently either \
+                (from {:?})", name, used, path, entry.source_file));
+            stale += 1;
+        }
+    }
+    if staleness.is_none() {
+        output::note("the history doesn't go back far enough to tell which shortcuts are stale");
+    }
+    match (missing, stale) {
+        (0, 0) => output::note("no problems found"),
+        _ => output::note(format!("{} shortcut(s) lead nowhere, and {} are stale; 'goto prune' \
+            removes those which lead nowhere", missing, stale)),
+    }
+}
+
+/// Remove shortcuts leading to directories which don't exist from the given configuration files,
+/// asking about each one unless `yes` is set. Pinned shortcuts are left alone unless
+/// `include_pinned` is set.
+fn prune_files(files: &[PathBuf], yes: bool, include_pinned: bool) -> Result<(), String> {
+    for path in files {
+        if !path.is_file() {
+            return Err(format!("{:?} is not a file", path));
+        }
+        edit::edit_config(path, |config_toml| {
+            let mut removed = 0;
+            for (context_key, name) in missing_entries(path, config_toml)? {
+                let table = match &context_key {
+                    None => &mut *config_toml,
+                    Some(key) => match config_toml.get_mut(key) {
+                        Some(toml::Value::Table(t)) => t,
+                        _ => continue,
+                    },
+                };
+                let shown = match &context_key {
+                    None => name.clone(),
+                    Some(key) => format!("{}.{}", key, name),
+                };
+                if table.get(&name).is_some_and(is_pinned) && !include_pinned {
+                    output::warn("skipped",
+                        format!("skipping {} in {:?}: it's pinned", shown, path));
+                    continue;
+                }
+                let question = format!("{} in {:?} leads to {}, which doesn't exist. Remove it?",
+                    shown, path, table[&name]);
+                if yes || confirm(&question) {
+                    table.remove(&name);
+                    output::note(format!("removed {} from {:?}", shown, path));
+                    removed += 1;
+                }
+            }
+            Ok(removed > 0)
+        })?;
+    }
+    Ok(())
+}
+
+/// If the path written as `s` in a configuration file is `old` or under it, rewrite it to be under
+/// `new` instead, written the same way: starting with "~/", relative to `relative_to`, or absolute.
+/// Paths with placeholders are left alone.
+fn rename_path_string(s: &str, relative_to: &Path, old: &Path, new: &Path, home: &Path)
+    -> Option<String>
+{
+    if s.contains('{') || s.starts_with('@') || s.starts_with("ssh://") {
+        return None;
+    }
+    let path = parse_toml_as_path(&toml::Value::String(s.to_owned()), relative_to).ok()?;
+    let rest = normalize_lexically(&path).strip_prefix(old).ok()?.to_owned();
+    let renamed = if rest.as_os_str().is_empty() { new.to_owned() } else { new.join(rest) };
+    let written = if s == "~" || s.starts_with("~/") || s.starts_with("~\\") {
+        config_path_string(&renamed, home)
+    } else if Path::new(s).is_relative() {
+        match renamed.strip_prefix(relative_to) {
+            Ok(rel) if !rel.as_os_str().is_empty() => rel.display().to_string(),
+            _ => renamed.display().to_string(),
+        }
+    } else {
+        renamed.display().to_string()
+    };
+    Some(written)
+}
+
+/// Rewrite the paths in a shortcut's definition which are under `old` to be under `new`, adding
+/// each change made to `changes`, as the path before and after.
+fn rename_dest_paths(
+    value: &mut toml::Value,
+    relative_to: &Path,
+    (old, new, home): (&Path, &Path, &Path),
+    changes: &mut Vec<(String, String)>,
+) {
+    match value {
+        toml::Value::String(s) => {
+            if let Some(renamed) = rename_path_string(s, relative_to, old, new, home) {
+                changes.push((std::mem::replace(s, renamed.clone()), renamed));
+            }
+        }
+        toml::Value::Array(alternatives) => {
+            for alternative in alternatives {
+                rename_dest_paths(alternative, relative_to, (old, new, home), changes);
+            }
+        }
+        // A container's path is inside the container.
+        toml::Value::Table(table) if !table.contains_key("container") => {
+            if let Some(path) = table.get_mut("path") {
+                rename_dest_paths(path, relative_to, (old, new, home), changes);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Rewrite the paths under `old` in a table of shortcuts and contexts to be under `new`: the
+/// shortcuts' destinations, and the contexts' own paths, and those within them. `is_context` tells
+/// which tables in it are contexts. Pinned shortcuts are left alone unless `include_pinned` is set.
+/// Each change made is added to `changes`.
+fn rename_mapping_paths(
+    table: &mut toml::value::Table,
+    is_context: &dyn Fn(&toml::value::Table) -> bool,
+    relative_to: &Path,
+    (paths, include_pinned): ((&Path, &Path, &Path), bool),
+    changes: &mut Vec<(String, String)>,
+) {
+    let (old, new, home) = paths;
+    let reserved = [SETTINGS_KEY, PROFILES_KEY, HOSTS_KEY, WORKSPACES_KEY, VERSION_KEY, CD_CMD_KEY];
+    let keys = table.keys()
+        .filter(|key| !reserved.contains(&key.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    for key in keys {
+        let mut value = table.remove(&key).unwrap();
+        let key = match &mut value {
+            toml::Value::Table(context) if is_context(context) => {
+                let key = match rename_path_string(&key, relative_to, old, new, home) {
+                    Some(renamed) => {
+                        changes.push((key, renamed.clone()));
+                        renamed
+                    }
+                    None => key,
+                };
+                // Relative paths in the context are relative to where it is now.
+                let context_path = parse_toml_as_path(&toml::Value::String(key.clone()),
+                    relative_to).unwrap();
+                let is_nested_context = |t: &toml::value::Table| !is_dest_table(t);
+                rename_mapping_paths(context, &is_nested_context, &context_path,
+                    (paths, include_pinned), changes);
+                key
+            }
+            value if is_pinned(value) && !include_pinned => {
+                let mut skipped = vec![];
+                rename_dest_paths(&mut value.clone(), relative_to, paths, &mut skipped);
+                if !skipped.is_empty() {
+                    output::warn("skipped", format!("skipping {}: it's pinned", key));
+                }
+                key
+            }
+            value => {
+                rename_dest_paths(value, relative_to, paths, changes);
+                key
+            }
+        };
+        table.insert(key, value);
+    }
+}
+
+/// Rewrite the paths in the given configuration files which are `old` or under it to be under
+/// `new` instead, after moving a directory. With `dry_run`, only report what would change.
+/// Pinned shortcuts are left alone unless `include_pinned` is set.
+fn rename_paths(
+    files: &[PathBuf],
+    (old, new, home): (&Path, &Path, &Path),
+    include_pinned: bool,
+    dry_run: bool,
+) -> Result<(), String> {
+    for path in files {
+        if !path.is_file() {
+            return Err(format!("{:?} is not a file", path));
+        }
+        let dir = path.parent().unwrap();
+        edit::edit_config(path, |config_toml| {
+            let version = config_version(config_toml);
+            let is_context = |t: &toml::value::Table| !is_entry_table(t, version);
+            let mut changes = vec![];
+            for key in [PROFILES_KEY, HOSTS_KEY] {
+                if let Some(toml::Value::Table(profiles)) = config_toml.get_mut(key) {
+                    for (_, profile) in profiles.iter_mut() {
+                        if let toml::Value::Table(profile) = profile {
+                            rename_mapping_paths(profile, &is_context, dir,
+                                ((old, new, home), include_pinned), &mut changes);
+                        }
+                    }
+                }
+            }
+            rename_mapping_paths(config_toml, &is_context, dir, ((old, new, home), include_pinned),
+                &mut changes);
+            for (before, after) in &changes {
+                output::note(format!("{:?}: {} → {}", path, before, after));
+            }
+            Ok(!changes.is_empty() && !dry_run)
+        })?;
+    }
+    Ok(())
+}
+
+/// Register every repository found under `dir` as a shortcut in the given configuration file.
+fn scan(dir: &Path, depth: usize, ignore: &[String], config_path: &Path, home: &Path)
+    -> Result<(), String>
+{
+    if !dir.is_dir() {
+        return Err(format!("{:?} is not a directory", dir));
+    }
+    let entries = scan::find_repos(dir, depth, ignore)
+        .into_iter()
+        .filter_map(|repo| {
+            let name = repo.file_name()?.to_str()?.to_owned();
+            Some((name, repo))
+        })
+        .collect::<Vec<_>>();
+    add_config_entries(config_path, &entries, false, false, home)
+}
+
+/// Register every immediate subdirectory of `dir` as a shortcut in the given configuration file,
+/// named by its basename with `prefix` prepended. With `force`, existing shortcuts with the same
+/// names are replaced, and with `include_pinned`, pinned ones too.
+fn add_children(
+    dir: &Path,
+    prefix: &str,
+    (force, include_pinned): (bool, bool),
+    config_path: &Path,
+    home: &Path,
+) -> Result<(), String> {
+    let read_dir = std::fs::read_dir(dir)
+        .map_err(|e| format!("failed to read directory {:?}: {}", dir, e))?;
+    let mut entries = vec![];
+    for entry in read_dir.flatten() {
+        let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if name.starts_with('.') || !entry.path().is_dir() {
+            continue;
+        }
+        entries.push((format!("{}{}", prefix, name), entry.path()));
+    }
+    entries.sort();
+    add_config_entries(config_path, &entries, force, include_pinned, home)
+}
+
+/// The start of the configuration file `goto new-config` creates, before its shortcuts.
+const NEW_CONFIG_HEADER: &str = "\
+# goto configuration. See 'goto --help' for everything that can go here.
+
+# Shortcuts, available anywhere under this directory: 'goto <name>' goes to
+# the path, which is relative to this directory unless it's absolute or starts
+# with ~/.
+";
+
+/// The example shortcuts in the file `goto new-config` creates, if it isn't seeded with real ones.
+const NEW_CONFIG_EXAMPLES: &str = "\
+#docs = \"docs\"
+#notes = \"~/notes\"
+
+# The shortcut used when goto is given no name.
+#\"*\" = \"src\"
+";
+
+/// The end of the configuration file `goto new-config` creates.
+const NEW_CONFIG_FOOTER: &str = "
+# A context: shortcuts only available under the given directory, with paths
+# relative to it, which override the ones above.
+#[\"src/app\"]
+#\"*\" = \"lib\"
+#tests = \"test/unit\"
+";
+
+/// Create a configuration file in `dir`, with commented examples of what can go in it. With
+/// `seed`, it has a shortcut for each immediate subdirectory of `dir` too. Returns its path.
+fn new_config(dir: &Path, seed: bool) -> Result<PathBuf, String> {
+    let config_path = find_config_file(dir);
+    let _lock = edit::lock(&config_path)
+        .map_err(|e| format!("failed to lock configuration {:?}: {}", config_path, e))?;
+    if config_path.exists() {
+        return Err(format!("{:?} already exists", config_path));
+    }
+
+    let mut text = NEW_CONFIG_HEADER.to_owned();
+    if seed {
+        let read_dir = std::fs::read_dir(dir)
+            .map_err(|e| format!("failed to read directory {:?}: {}", dir, e))?;
+        let mut names = read_dir.flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| !name.starts_with('.'))
+            .collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let quoted = toml::Value::String(name.clone()).to_string();
+            let bare = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+            text += &format!("{} = {}\n", if bare { &name } else { &quoted }, quoted);
+        }
+    } else {
+        text += NEW_CONFIG_EXAMPLES;
+    }
+    text += NEW_CONFIG_FOOTER;
+
+    let write_error = |e| format!("failed to write configuration {:?}: {}", config_path, e);
+    backup::save(&config_path, None).map_err(write_error)?;
+    edit::write_atomically(&config_path, text.as_bytes()).map_err(write_error)?;
+    daemon::invalidate();
+    Ok(config_path)
+}
+
+type PathMapping = BTreeMap<String, PathMappingEntry>;
+
+#[derive(Debug, Clone)]
+struct PathMappingEntry {
+    dest: Destination,
+    source_file: PathBuf,
+    /// The context the entry was defined in, or None if it's a top-level entry.
+    context: Option<PathBuf>,
+    desc: Option<String>,
+    tags: Vec<String>,
+    /// Where the entry stands when another configuration file defines the same name: the higher
+    /// priority wins, and for equal priorities, the usual precedence rules apply.
+    priority: i64,
+    /// Whether to create the destination directory if it doesn't exist, as with --create.
+    create: bool,
+    /// Whether to ask on the terminal before going there, because it's somewhere a stray command
+    /// would do harm.
+    confirm: bool,
+    /// A command which makes the destination available, when it's a directory that needs mounting.
+    /// Only allowed in the home configuration, like command destinations.
+    mount: Option<String>,
+    /// The command to change to the destination with, instead of the one given with --cmd, from
+    /// the entry or the context it's in.
+    cd_cmd: Option<String>,
+}
+
+impl PathMappingEntry {
+    /// An entry for `dest`, defined in `source_file`, with nothing else about it set.
+    fn new(dest: Destination, source_file: PathBuf) -> Self {
+        PathMappingEntry {
+            dest,
+            source_file,
+            context: None,
+            desc: None,
+            tags: vec![],
+            priority: 0,
+            create: false,
+            confirm: false,
+            mount: None,
+            cd_cmd: None,
+        }
+    }
+}
+
+/// Where a shortcut takes you.
+#[derive(Debug, Clone)]
+enum Destination {
+    /// A directory on this machine.
+    Local(PathBuf),
+    /// A directory on another machine, reached over SSH. `host` may include a "user@" prefix.
+    Ssh { host: String, path: String },
+    /// A directory inside a running container, entered by running `shell` in it.
+    Container { name: String, path: String, shell: String },
+    /// A directory on this machine, printed by running a shell command in `dir`. Only allowed in
+    /// the home configuration, and resolved with `run_dest_command` when it's used.
+    Command { cmd: String, dir: PathBuf },
+    /// Wherever another shortcut leads, written as "@name". Resolved with `resolve_dest`.
+    Alias(String),
+    /// The first of several destinations, written as an array, which exists on this machine, or if
+    /// none do, the first. Resolved with `resolve_dest`, so only shortcuts which are used are
+    /// checked.
+    Alternatives(Vec<Destination>),
+    /// The most recently modified subdirectory of where the destination within leads, or if it has
+    /// none, that directory itself. Written with "newest-child = true", and resolved with
+    /// `resolve_dest`.
+    NewestChild(Box<Destination>),
+    /// The directory under `under` most recently visited, if any still exists, or else where
+    /// `fallback` leads. Written with "last-visited = true", and resolved with `resolve_dest`.
+    LastVisited { under: PathBuf, fallback: Box<Destination> },
+    /// The nearest directory, `from` or above it, with one of `markers` in it: the built-in "root"
+    /// shortcut, without --git. Resolved with `resolve_dest`, so the directories above aren't
+    /// looked in unless it's used.
+    NearestRoot { from: PathBuf, markers: Vec<String> },
+    /// Nowhere: written as `false`, this hides the shortcuts with the same name which it takes
+    /// precedence over.
+    Disabled,
+}
+
+impl std::fmt::Display for Destination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Destination::Local(path) => write!(f, "{:?}", path),
+            Destination::Ssh { host, path } => write!(f, "ssh://{}:{}", host, path),
+            Destination::Container { name, path, .. } => write!(f, "container {}:{}", name, path),
+            Destination::Command { cmd, .. } => write!(f, "$({})", cmd),
+            Destination::Alias(name) => write!(f, "@{}", name),
+            Destination::Alternatives(alternatives) => {
+                let alternatives = alternatives.iter().map(Destination::to_string)
+                    .collect::<Vec<_>>();
+                write!(f, "the first of {}", alternatives.join(", "))
+            }
+            Destination::NewestChild(dest) => write!(f, "the newest directory in {}", dest),
+            Destination::LastVisited { under, fallback } => {
+                write!(f, "the last visited directory in {:?}, or else {}", under, fallback)
+            }
+            Destination::NearestRoot { markers, .. } => {
+                write!(f, "the nearest directory containing {}", markers.join(" or "))
+            }
+            Destination::Disabled => write!(f, "(disabled)"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Configuration {
+    global: PathMapping,
+    contexts: BTreeMap<PathBuf, PathMapping>,
+    /// Shortcuts which come above all the others, whatever the context: the temporary ones, from
+    /// 'goto add --temp'.
+    temporary: PathMapping,
+    settings: Settings,
+    /// What the file's table for this host overrides, which is made a layer of its own, above all
+    /// the files.
+    host_overrides: Option<Box<Configuration>>,
+    /// The shortcuts each workspace opens, by the workspace's name.
+    workspaces: BTreeMap<String, Vec<String>>,
+}
+
+/// A shortcut to search under for directories which aren't defined as shortcuts, with how strongly
+/// to prefer what's found under it.
+#[derive(Debug, Clone)]
+struct SearchRoot {
+    name: String,
+    weight: f64,
+}
+
+/// Options set in the `[settings]` table of a configuration file. Unset options are `None`, so
+/// that combining configurations only overrides the ones which are actually set.
+#[derive(Debug, Default, Clone)]
+struct Settings {
+    /// Whether a name which isn't a shortcut, but is an existing directory, is gone to directly.
+    path_fallback: Option<bool>,
+    /// Shortcuts to search under for a directory matching a name which isn't defined.
+    search_roots: Option<Vec<SearchRoot>>,
+    /// Whether to check that extra path components lead to an existing directory.
+    verify_extra: Option<bool>,
+    /// Whether to export GOTO_NAME and GOTO_LAST along with the directory change.
+    export_vars: Option<bool>,
+    /// Whether to set the terminal's title to the shortcut name.
+    terminal_title: Option<bool>,
+    /// Whether to tell the terminal the new working directory, with the OSC 7 escape sequence.
+    report_cwd: Option<bool>,
+    /// How to compare the case of shortcut names.
+    name_case: Option<NameCase>,
+    /// Whether to resolve symbolic links in destinations before outputting them.
+    resolve_symlinks: Option<bool>,
+    /// Whether names and paths written in different Unicode normalization forms match.
+    normalize_unicode: Option<bool>,
+    /// Whether contexts apply where the CWD is in them after resolving symbolic links.
+    resolve_contexts: Option<bool>,
+    /// Whether the other configuration files take precedence over the home configuration,
+    /// instead of the other way around. Only the home configuration's setting counts.
+    home_lowest: Option<bool>,
+    /// What to do when nothing matches the name given.
+    fallback: Option<Fallback>,
+    /// Whether to record jumps in the history, for `goto history`.
+    record_history: Option<bool>,
+    /// More configuration files to read, at the lowest precedence.
+    overlays: Option<Vec<PathBuf>>,
+    /// The names of files or directories which mark where the "root" shortcut goes.
+    root_markers: Option<Vec<String>>,
+    /// What to do when goto is run directly, without the shell function.
+    unwrapped: Option<Unwrapped>,
+    /// How long, in seconds, a destination can go unused and unmodified before it's stale.
+    stale_after: Option<u64>,
+    /// Templates for messages, by the kind of message they're for.
+    messages: Option<BTreeMap<String, String>>,
+}
+
+/// How long a destination can go unused and unmodified before it's stale, unless the
+/// "stale-after" setting says otherwise: 26 weeks.
+const DEFAULT_STALE_AFTER: u64 = 26 * 7 * 24 * 60 * 60;
+
+/// What to do when goto's output goes straight to the terminal, so nothing will run the command
+/// it prints, set with the "unwrapped" setting.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Unwrapped {
+    /// Print the command anyway, with a hint about setting up the shell function.
+    #[default]
+    Hint,
+    /// Start a new shell in the destination directory instead.
+    Subshell,
+}
+
+/// What to do when a name doesn't match anything, set with the "fallback" setting.
+#[derive(Debug, Clone)]
+enum Fallback {
+    /// Fail with an error, and a non-zero exit status.
+    Error,
+    /// Go somewhere else instead, exiting with `FALLBACK_EXIT_CODE`.
+    Go(Destination),
+}
+
+/// The exit status when the "fallback" setting's destination was used, so that scripts can tell
+/// it from a match.
+const FALLBACK_EXIT_CODE: i32 = 3;
+
+/// How the case of shortcut names is compared when looking them up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum NameCase {
+    /// Names must match exactly.
+    #[default]
+    Sensitive,
+    /// Names match regardless of case.
+    Insensitive,
+    /// Names match regardless of case, unless the name asked for has uppercase letters in it.
+    Smart,
+}
+
+impl NameCase {
+    /// Whether a name which doesn't match exactly can match ignoring case.
+    fn ignores_case_of(self, name: &str) -> bool {
+        match self {
+            NameCase::Sensitive => false,
+            NameCase::Insensitive => true,
+            NameCase::Smart => !name.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+/// Find an entry by name, preferring an exact match, then one differing only in case or Unicode
+/// normalization form, as allowed by the settings.
+fn find_name<'a>(map: &'a PathMapping, name: &str, settings: &Settings)
+    -> Option<&'a PathMappingEntry>
+{
+    if let Some(entry) = map.get(name) {
+        return Some(entry);
+    }
+    let ignore_case = settings.name_case.unwrap_or_default().ignores_case_of(name);
+    let normalize = settings.normalize_unicode.unwrap_or(true);
+    if !ignore_case && !normalize {
+        return None;
+    }
+    let fold = |s: &str| {
+        let s = if normalize { s.nfc().collect::<String>() } else { s.to_owned() };
+        if ignore_case { s.to_lowercase() } else { s }
+    };
+    let name = fold(name);
+    map.iter().find(|(k, _)| fold(k) == name).map(|(_, entry)| entry)
+}
+
+/// A path with its text in Unicode's composed normalization form (NFC), for comparing paths
+/// which may be written in different forms, as macOS gives decomposed ones. Paths which aren't
+/// valid Unicode are returned as they are.
+fn nfc_path(path: &Path) -> PathBuf {
+    match path.to_str() {
+        Some(s) => PathBuf::from(s.nfc().collect::<String>()),
+        None => path.to_owned(),
+    }
+}
+
+/// Whether a context for `context_path` applies in `cwd`.
+fn context_applies(context_path: &Path, cwd: &Path, settings: &Settings) -> bool {
+    if cwd.starts_with(context_path)
+        || (settings.normalize_unicode.unwrap_or(true)
+            && nfc_path(cwd).starts_with(nfc_path(context_path)))
+    {
+        return true;
+    }
+    if settings.resolve_contexts.unwrap_or(false) {
+        if let (Ok(cwd), Ok(context_path)) = (cwd.canonicalize(), context_path.canonicalize()) {
+            return cwd.starts_with(context_path);
+        }
+    }
+    false
+}
+
+/// The environment variable which can name overlays: configuration files read at the lowest
+/// precedence, like those the "overlays" setting names.
+const OVERLAYS_VAR: &str = "GOTO_OVERLAYS";
+
+/// The name of the top-level table holding settings rather than a context.
+const SETTINGS_KEY: &str = "settings";
+
+/// The name of the top-level table holding profiles: alternative sets of shortcuts, contexts, and
+/// settings, one of which can be made active with --profile or $GOTO_PROFILE.
+const PROFILES_KEY: &str = "profile";
+
+/// The active profile, if any.
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// The name of the top-level table holding tables of overrides for particular machines, by host
+/// name, so that one configuration file can be shared between machines where paths differ.
+const HOSTS_KEY: &str = "hosts";
+
+/// Take the profiles out of a configuration file's contents, and if one of them is active, lay its
+/// contents over the rest. Contexts and settings in it are merged with those outside it, key by
+/// key; anything else replaces what's outside.
+fn apply_profile(config_toml: &mut toml::value::Table) -> Result<(), Diagnostic> {
+    let profiles = match config_toml.remove(PROFILES_KEY) {
+        None => return Ok(()),
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(other) => {
+            return Err(Diagnostic::new("config-invalid", format!(
+                "type error: expected {:?} to be a table, not {}", PROFILES_KEY, other.type_str()))
+                .with_key(PROFILES_KEY));
+        }
+    };
+    let Some(name) = PROFILE.get() else {
+        return Ok(());
+    };
+    let profile = match profiles.get(name) {
+        None => return Ok(()),
+        Some(toml::Value::Table(profile)) => profile.clone(),
+        Some(other) => {
+            let key = format!("{}.{}", PROFILES_KEY, name);
+            return Err(Diagnostic::new("config-invalid", format!(
+                "type error: expected {:?} to be a table, not {}", key, other.type_str()))
+                .with_key(&key));
+        }
+    };
+
+    let version = config_version(config_toml);
+    for (k, v) in profile {
+        match (config_toml.get_mut(&k), v) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(overlay))
+                if !is_entry_table(existing, version) && !is_entry_table(&overlay, version) =>
+            {
+                existing.extend(overlay);
+            }
+            (_, v) => {
+                config_toml.insert(k, v);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The name of the top-level table holding workspaces: sets of shortcuts which
+/// `goto workspace <name>` opens all at once, each in a tmux window of its own.
+const WORKSPACES_KEY: &str = "workspace";
+
+/// Read the workspaces from their table in a configuration file.
+fn process_workspaces(table: &toml::value::Table)
+    -> Result<BTreeMap<String, Vec<String>>, Diagnostic>
+{
+    let mut workspaces = BTreeMap::new();
+    for (name, value) in table {
+        let key = format!("{}.{}", WORKSPACES_KEY, name);
+        let invalid = |msg: String| {
+            Diagnostic::new("config-invalid", format!("error at {}: {}", key, msg)).with_key(&key)
+        };
+        let toml::Value::Table(workspace) = value else {
+            return Err(invalid(format!("expected a table, not {}", value.type_str())));
+        };
+        let shortcuts = get_table_str_list(workspace, "shortcuts").map_err(invalid)?;
+        if shortcuts.is_empty() {
+            return Err(invalid("expected \"shortcuts\" to list at least one shortcut".to_owned()));
+        }
+        workspaces.insert(name.clone(), shortcuts);
+    }
+    Ok(workspaces)
+}
+
+/// Take the tables of overrides for particular machines out of a configuration file's contents,
+/// and return the one for this machine, if there is one. It can be named by the full host name or
+/// just the part before the first dot.
+fn take_host_table(config_toml: &mut toml::value::Table)
+    -> Result<Option<toml::value::Table>, Diagnostic>
+{
+    let type_error = |key: &str, other: &toml::Value| {
+        Diagnostic::new("config-invalid", format!(
+            "type error: expected {:?} to be a table, not {}", key, other.type_str()))
+            .with_key(key)
+    };
+    let mut hosts = match config_toml.remove(HOSTS_KEY) {
+        None => return Ok(None),
+        Some(toml::Value::Table(hosts)) => hosts,
+        Some(other) => return Err(type_error(HOSTS_KEY, &other)),
+    };
+    let full_name = host_name();
+    if full_name.is_empty() {
+        return Ok(None);
+    }
+    let short_name = full_name.split('.').next().unwrap_or_default();
+    let name = if hosts.contains_key(full_name) { full_name } else { short_name };
+    match hosts.remove(name) {
+        None => Ok(None),
+        Some(toml::Value::Table(table)) => Ok(Some(table)),
+        Some(other) => Err(type_error(&format!("{}.{:?}", HOSTS_KEY, name), &other)),
+    }
+}
+
+/// Make overrides for this machine keep the details of the entries they override, except for
+/// those they give themselves, so that they only need to give a destination. They also get at
+/// least the same priority, so that they win.
+fn inherit_entry_details(overrides: &mut PathMapping, originals: &PathMapping) {
+    for (name, entry) in overrides.iter_mut() {
+        let Some(original) = originals.get(name) else {
+            continue;
+        };
+        if entry.desc.is_none() {
+            entry.desc.clone_from(&original.desc);
+        }
+        if entry.tags.is_empty() {
+            entry.tags.clone_from(&original.tags);
+        }
+        if entry.mount.is_none() {
+            entry.mount.clone_from(&original.mount);
+        }
+        if entry.cd_cmd.is_none() {
+            entry.cd_cmd.clone_from(&original.cd_cmd);
+        }
+        entry.create |= original.create;
+        entry.confirm |= original.confirm;
+        entry.priority = entry.priority.max(original.priority);
+    }
+}
+
+/// Make a diagnostic for an invalid setting.
+fn setting_error(name: &str, msg: String) -> Diagnostic {
+    let key = format!("{}.{}", SETTINGS_KEY, name);
+    Diagnostic::new("config-invalid", format!("error at {}: {}", key, msg)).with_key(key)
+}
+
+/// Get a setting's value as a bool.
+fn setting_as_bool(name: &str, value: &toml::Value) -> Result<bool, Diagnostic> {
+    match value {
+        toml::Value::Boolean(b) => Ok(*b),
+        _ => Err(setting_error(name, format!("expected a boolean, not {}", value.type_str()))),
+    }
+}
+
+/// Get a setting's value as a list of strings.
+fn setting_as_string_list(name: &str, value: &toml::Value) -> Result<Vec<String>, Diagnostic> {
+    let type_error = |t: &toml::Value| {
+        setting_error(name, format!("expected an array of strings, not {}", t.type_str()))
+    };
+    let toml::Value::Array(array) = value else {
+        return Err(type_error(value));
+    };
+    array.iter()
+        .map(|item| match item {
+            toml::Value::String(s) => Ok(s.clone()),
+            _ => Err(type_error(item)),
+        })
+        .collect()
+}
+
+/// Get the "search-roots" setting's value: an array of shortcut names, or tables with the name as
+/// "root", and a "weight", which defaults to 1.
+fn setting_as_search_roots(name: &str, value: &toml::Value)
+    -> Result<Vec<SearchRoot>, Diagnostic>
+{
+    let type_error = |t: &toml::Value| {
+        setting_error(name, format!("expected an array of shortcut names or tables, not {}",
+            t.type_str()))
+    };
+    let toml::Value::Array(array) = value else {
+        return Err(type_error(value));
+    };
+    array.iter()
+        .map(|item| match item {
+            toml::Value::String(root) => Ok(SearchRoot { name: root.clone(), weight: 1.0 }),
+            toml::Value::Table(table) => {
+                let root = get_table_str(table, "root")
+                    .map_err(|msg| setting_error(name, msg))?
+                    .ok_or_else(|| setting_error(name, "expected a \"root\" key".to_owned()))?;
+                let weight = match table.get("weight") {
+                    None => 1.0,
+                    Some(toml::Value::Float(weight)) if *weight > 0.0 => *weight,
+                    Some(toml::Value::Integer(weight)) if *weight > 0 => *weight as f64,
+                    Some(other) => return Err(setting_error(name, format!(
+                        "expected \"weight\" to be a positive number, not {}", other))),
+                };
+                Ok(SearchRoot { name: root.to_owned(), weight })
+            }
+            _ => Err(type_error(item)),
+        })
+        .collect()
+}
+
+/// Process the `[settings]` table of a configuration file. Relative paths in it are interpreted
+/// relative to `relative_to`.
+fn process_settings(table: &toml::value::Table, relative_to: &Path)
+    -> Result<Settings, Diagnostic>
+{
+    let mut settings = Settings::default();
+    for (name, value) in table {
+        match name.as_str() {
+            "path-fallback" => settings.path_fallback = Some(setting_as_bool(name, value)?),
+            "search-roots" => {
+                settings.search_roots = Some(setting_as_search_roots(name, value)?);
+            }
+            "verify-extra" => settings.verify_extra = Some(setting_as_bool(name, value)?),
+            "export-vars" => settings.export_vars = Some(setting_as_bool(name, value)?),
+            "terminal-title" => settings.terminal_title = Some(setting_as_bool(name, value)?),
+            "report-cwd" => settings.report_cwd = Some(setting_as_bool(name, value)?),
+            "resolve-symlinks" => {
+                settings.resolve_symlinks = Some(setting_as_bool(name, value)?);
+            }
+            "normalize-unicode" => {
+                settings.normalize_unicode = Some(setting_as_bool(name, value)?);
+            }
+            "resolve-contexts" => {
+                settings.resolve_contexts = Some(setting_as_bool(name, value)?);
+            }
+            "home-lowest" => {
+                settings.home_lowest = Some(setting_as_bool(name, value)?);
+            }
+            "overlays" => {
+                settings.overlays = Some(setting_as_string_list(name, value)?
+                    .into_iter()
+                    .map(|path| parse_toml_as_path(&toml::Value::String(path), relative_to))
+                    .collect::<Result<_, _>>()
+                    .map_err(|msg| setting_error(name, msg))?);
+            }
+            "root-markers" => {
+                settings.root_markers = Some(setting_as_string_list(name, value)?);
+            }
+            "stale-after" => {
+                settings.stale_after = Some(value.as_str()
+                    .and_then(history::parse_duration)
+                    .ok_or_else(|| setting_error(name, "expected a number of minutes, hours, \
+                        days, or weeks, like \"26w\"".to_owned()))?);
+            }
+            "messages" => {
+                let table = value.as_table().ok_or_else(|| setting_error(name,
+                    format!("expected a table of message kinds, not {}", value.type_str())))?;
+                settings.messages = Some(table.iter()
+                    .map(|(kind, template)| template.as_str()
+                        .map(|template| (kind.clone(), template.to_owned()))
+                        .ok_or_else(|| format!("expected a template string for {:?}, not {}",
+                            kind, template.type_str())))
+                    .collect::<Result<_, _>>()
+                    .map_err(|msg| setting_error(name, msg))?);
+            }
+            "unwrapped" => {
+                settings.unwrapped = Some(match value.as_str() {
+                    Some("hint") => Unwrapped::Hint,
+                    Some("subshell") => Unwrapped::Subshell,
+                    _ => return Err(setting_error(name,
+                        "expected \"hint\" or \"subshell\"".to_owned())),
+                });
+            }
+            "record-history" => {
+                settings.record_history = Some(setting_as_bool(name, value)?);
+            }
+            "fallback" => {
+                settings.fallback = Some(match value {
+                    toml::Value::String(s) if s == "error" => Fallback::Error,
+                    toml::Value::String(_) => Fallback::Go(parse_toml_as_dest(value, relative_to)
+                        .map_err(|msg| setting_error(name, msg))?),
+                    _ => return Err(setting_error(name,
+                        format!("expected a string, not {}", value.type_str()))),
+                });
+            }
+            "name-case" => {
+                settings.name_case = Some(match value.as_str() {
+                    Some("sensitive") => NameCase::Sensitive,
+                    Some("insensitive") => NameCase::Insensitive,
+                    Some("smart") => NameCase::Smart,
+                    _ => return Err(setting_error(name,
+                        "expected \"sensitive\", \"insensitive\", or \"smart\"".to_owned())),
+                });
+            }
+            _ => return Err(setting_error(name, "unknown setting".to_owned())),
+        }
+    }
+    Ok(settings)
+}
+
+/// Combine two sets of settings. The ones set in `overlay` take precedence.
+fn combine_settings(combined: &mut Settings, overlay: Settings) {
+    if overlay.path_fallback.is_some() {
+        combined.path_fallback = overlay.path_fallback;
+    }
+    if overlay.search_roots.is_some() {
+        combined.search_roots = overlay.search_roots;
+    }
+    if overlay.verify_extra.is_some() {
+        combined.verify_extra = overlay.verify_extra;
+    }
+    if overlay.export_vars.is_some() {
+        combined.export_vars = overlay.export_vars;
+    }
+    if overlay.terminal_title.is_some() {
+        combined.terminal_title = overlay.terminal_title;
+    }
+    if overlay.report_cwd.is_some() {
+        combined.report_cwd = overlay.report_cwd;
+    }
+    if overlay.name_case.is_some() {
+        combined.name_case = overlay.name_case;
+    }
+    if overlay.resolve_symlinks.is_some() {
+        combined.resolve_symlinks = overlay.resolve_symlinks;
+    }
+    if overlay.normalize_unicode.is_some() {
+        combined.normalize_unicode = overlay.normalize_unicode;
+    }
+    if overlay.resolve_contexts.is_some() {
+        combined.resolve_contexts = overlay.resolve_contexts;
+    }
+    if overlay.home_lowest.is_some() {
+        combined.home_lowest = overlay.home_lowest;
+    }
+    if overlay.fallback.is_some() {
+        combined.fallback = overlay.fallback;
+    }
+    if overlay.record_history.is_some() {
+        combined.record_history = overlay.record_history;
+    }
+    if overlay.overlays.is_some() {
+        combined.overlays = overlay.overlays;
+    }
+    if overlay.root_markers.is_some() {
+        combined.root_markers = overlay.root_markers;
+    }
+    if overlay.unwrapped.is_some() {
+        combined.unwrapped = overlay.unwrapped;
+    }
+    if overlay.stale_after.is_some() {
+        combined.stale_after = overlay.stale_after;
+    }
+    // Templates are combined kind by kind, so a file can restyle one kind of message without
+    // undoing the others' templates.
+    if let Some(messages) = overlay.messages {
+        combined.messages.get_or_insert_with(BTreeMap::new).extend(messages);
+    }
+}
+
+/// The directory goto is run in, as it's resolved in `main`, with --cwd and $PWD taken into
+/// account, for the {branch} placeholder.
+static CWD: OnceLock<PathBuf> = OnceLock::new();
+
+/// The branch checked out in the git repository containing `cwd`, or for a detached HEAD, the
+/// abbreviated commit hash. Found by reading the repository's HEAD file.
+fn current_branch(cwd: &Path) -> Option<String> {
+    let dot_git = find_repo_root(cwd)?.join(".git");
+    // In worktrees and submodules, .git is a file pointing at the real git directory.
+    let git_dir = if dot_git.is_file() {
+        let pointer = std::fs::read_to_string(&dot_git).ok()?;
+        dot_git.parent()?.join(pointer.strip_prefix("gitdir:")?.trim())
+    } else {
+        dot_git
+    };
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(head_ref) => Some(head_ref.strip_prefix("refs/heads/").unwrap_or(head_ref).to_owned()),
+        None => head.get(.. 7).map(str::to_owned),
+    }
+}
+
+/// Replace the placeholders {yyyy}, {yy}, {mm}, and {dd} in a configured path with today's date,
+/// in the local time zone, and {branch} with the git branch checked out where goto is run.
+/// Outside a git repository, {branch} is left as it is.
+fn expand_placeholders(s: &str) -> String {
+    if !s.contains('{') {
+        return s.to_owned();
+    }
+    let today = chrono::Local::now();
+    let mut expanded = s.replace("{yyyy}", &format!("{:04}", today.year()))
+        .replace("{yy}", &format!("{:02}", today.year().rem_euclid(100)))
+        .replace("{mm}", &format!("{:02}", today.month()))
+        .replace("{dd}", &format!("{:02}", today.day()));
+    if expanded.contains("{branch}") {
+        if let Some(branch) = CWD.get().and_then(|cwd| current_branch(cwd)) {
+            expanded = expanded.replace("{branch}", &branch);
+        }
+    }
+    expanded
+}
+
+/// Whether what a configuration file says can depend on when and where goto is run, or with which
+/// profile: whether its text has placeholders or profiles in it.
+fn varies_by_run(text: &str) -> bool {
+    ["{yyyy}", "{yy}", "{mm}", "{dd}", "{branch}", PROFILES_KEY].iter()
+        .any(|part| text.contains(part))
+}
+
+/// Make the given TOML value into an absolute path. It should be a string, otherwise an error is
+/// returned. If the path is relative, it is made absolute by interpreting it relative to the given
+/// path, or to the user's home directory if it starts with "~/".
+fn parse_toml_as_path(t: &toml::Value, relative_to: &Path) -> Result<PathBuf, String> {
+    if let toml::Value::String(ref s) = *t {
+        let s = expand_placeholders(s);
+        let path: PathBuf = if s.starts_with("~/") || s.starts_with("~\\") {
+            dirs::home_dir().unwrap().join(Path::new(&s[2..]))
+        } else if s == "~" {
+            dirs::home_dir().unwrap()
+        } else {
+            // note: this handles absolute paths correctly, by not using `relative_to` at all
+            // (except for Windows, where the drive letter of `relative_to` may be considered).
+            relative_to.join(Path::new(&s))
+        };
+        Ok(path)
+    } else {
+        Err(format!("type error: expected a string, not {}", t.type_str()))
+    }
+}
+
+/// The newest version of the configuration format, set with a top-level `version = N`. Files
+/// without it are version 1.
+///
+/// Version 2 allows top-level shortcuts to be written as tables with a "path" key. In version 1,
+/// such a table is a context which happens to define a shortcut named "path".
+const CONFIG_VERSION: i64 = 2;
+
+/// The top-level key holding the configuration format version.
+const VERSION_KEY: &str = "version";
+
+/// The key in a context table holding the command to change to its shortcuts' directories with.
+const CD_CMD_KEY: &str = "cd-cmd";
+
+/// Get the configuration format version declared in a configuration file.
+fn config_version(config_toml: &toml::value::Table) -> i64 {
+    match config_toml.get(VERSION_KEY) {
+        Some(toml::Value::Integer(version)) => *version,
+        _ => 1,
+    }
+}
+
+/// Whether the given TOML table describes a single shortcut, rather than a context, in a
+/// configuration file of the given format version.
+fn is_entry_table(t: &toml::value::Table, version: i64) -> bool {
+    let is_str = |key| matches!(t.get(key), Some(toml::Value::String(_)));
+    is_str("container") || (version >= 2 && (is_str("path") || is_str("cmd")))
+}
+
+/// Get an optional string field from a table describing a shortcut.
+fn get_table_str<'a>(t: &'a toml::value::Table, key: &str) -> Result<Option<&'a str>, String> {
+    match t.get(key) {
+        None => Ok(None),
+        Some(toml::Value::String(s)) => Ok(Some(s)),
+        Some(other) => Err(format!("type error: expected {:?} to be a string, not {}",
+            key, other.type_str())),
+    }
+}
+
+/// Get an optional boolean field from a table describing a shortcut, which defaults to false.
+fn get_table_bool(t: &toml::value::Table, key: &str) -> Result<bool, String> {
+    match t.get(key) {
+        None => Ok(false),
+        Some(toml::Value::Boolean(b)) => Ok(*b),
+        Some(other) => Err(format!("type error: expected {:?} to be a boolean, not {}",
+            key, other.type_str())),
+    }
+}
+
+/// Get an optional list of strings from a table describing a shortcut.
+fn get_table_str_list(t: &toml::value::Table, key: &str) -> Result<Vec<String>, String> {
+    let type_error = |v: &toml::Value| {
+        format!("type error: expected {:?} to be an array of strings, not {}", key, v.type_str())
+    };
+    match t.get(key) {
+        None => Ok(vec![]),
+        Some(toml::Value::Array(items)) => items.iter()
+            .map(|item| item.as_str().map(str::to_owned).ok_or_else(|| type_error(item)))
+            .collect(),
+        Some(other) => Err(type_error(other)),
+    }
+}
+
+/// The most recently modified subdirectory of `dir`, if it has any.
+fn newest_child(dir: &Path) -> Option<PathBuf> {
+    std::fs::read_dir(dir).ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let meta = path.metadata().ok()?;
+            meta.is_dir().then_some((meta.modified().ok()?, path))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// Parse the given TOML value as a shortcut destination. Strings of the form
+/// "ssh://[user@]host:/path" are remote destinations, tables with a "container" key are
+/// container destinations, and tables with a "cmd" key are command destinations; other tables
+/// take their destination from their "path" key, or with "newest-child = true", from its most
+/// recently modified subdirectory. With "last-visited = true", they lead to the directory under
+/// `relative_to` most recently visited, with "path" (if any) as the fallback. Arrays list
+/// alternatives, of which the first that exists on this machine is used. "@name" refers to
+/// another shortcut, and `false` disables the shortcut. Anything else is handled by
+/// `parse_toml_as_path`.
+fn parse_toml_as_dest(t: &toml::Value, relative_to: &Path) -> Result<Destination, String> {
+    if let toml::Value::Boolean(false) = *t {
+        return Ok(Destination::Disabled);
+    }
+    if let toml::Value::Table(ref table) = *t {
+        if let Some(name) = get_table_str(table, "container")? {
+            return Ok(Destination::Container {
+                name: name.to_owned(),
+                path: get_table_str(table, "path")?.unwrap_or("").to_owned(),
+                shell: get_table_str(table, "shell")?.unwrap_or("sh").to_owned(),
+            });
+        }
+        if let Some(cmd) = get_table_str(table, "cmd")? {
+            return Ok(Destination::Command { cmd: cmd.to_owned(), dir: relative_to.to_owned() });
+        }
+        let last_visited = get_table_bool(table, "last-visited")?;
+        return match table.get("path") {
+            Some(path @ (toml::Value::String(_) | toml::Value::Array(_))) => {
+                match parse_toml_as_dest(path, relative_to)? {
+                    dest if last_visited => Ok(Destination::LastVisited {
+                        under: relative_to.to_owned(),
+                        fallback: Box::new(dest),
+                    }),
+                    dest if get_table_bool(table, "newest-child")? => {
+                        Ok(Destination::NewestChild(Box::new(dest)))
+                    }
+                    dest => Ok(dest),
+                }
+            }
+            None if last_visited => Ok(Destination::LastVisited {
+                under: relative_to.to_owned(),
+                fallback: Box::new(Destination::Local(relative_to.to_owned())),
+            }),
+            Some(other) => Err(format!(
+                "type error: expected \"path\" to be a string or an array, not {}",
+                other.type_str())),
+            None => Err("expected a table with a \"path\", \"container\", or \"cmd\" key"
+                .to_owned()),
+        };
+    }
+    if let toml::Value::Array(ref alternatives) = *t {
+        if alternatives.is_empty() {
+            return Err("expected at least one path in the array".to_owned());
+        }
+        return alternatives.iter()
+            .map(|alternative| parse_toml_as_dest(alternative, relative_to))
+            .collect::<Result<_, _>>()
+            .map(Destination::Alternatives);
+    }
+    if let toml::Value::String(ref s) = *t {
+        if let Some(name) = s.strip_prefix('@') {
+            if name.is_empty() {
+                return Err("missing shortcut name after \"@\"".to_owned());
+            }
+            return Ok(Destination::Alias(name.to_owned()));
+        }
+        if let Some(remote) = s.strip_prefix("ssh://") {
+            let (host, path) = match remote.split_once(':') {
+                Some((host, path)) => (host, path),
+                None => (remote, ""),
+            };
+            if host.is_empty() {
+                return Err(format!("missing host name in {:?}", s));
+            }
+            return Ok(Destination::Ssh { host: host.to_owned(), path: path.to_owned() });
+        }
+    }
+    parse_toml_as_path(t, relative_to).map(Destination::Local)
+}
+
+/// Parse a shortcut definition: its destination, and if it's written as a table, its optional
+/// description, tags, priority, whether to create it, how to mount it, and the command to change
+/// to it with.
+fn parse_toml_as_entry(
+    t: &toml::Value,
+    relative_to: &Path,
+    source_file: &Path,
+    context: Option<&Path>,
+) -> Result<PathMappingEntry, String> {
+    let dest = parse_toml_as_dest(t, relative_to)?;
+    let mut entry = PathMappingEntry {
+        context: context.map(Path::to_owned),
+        ..PathMappingEntry::new(dest, source_file.to_owned())
+    };
+    if let toml::Value::Table(table) = t {
+        entry.desc = get_table_str(table, "desc")?.map(str::to_owned);
+        entry.tags = get_table_str_list(table, "tags")?;
+        entry.create = get_table_bool(table, "create")?;
+        entry.confirm = get_table_bool(table, "confirm")?;
+        entry.mount = get_table_str(table, "mount")?.map(str::to_owned);
+        entry.cd_cmd = get_table_str(table, "cd-cmd")?.map(str::to_owned);
+        // Only commands which edit configuration files look at it, but check it anyway.
+        get_table_bool(table, "pinned")?;
+        entry.priority = match table.get("priority") {
+            None => 0,
+            Some(toml::Value::Integer(priority)) => *priority,
+            Some(other) => return Err(format!(
+                "type error: expected \"priority\" to be an integer, not {}", other.type_str())),
+        };
+    }
+    Ok(entry)
+}
+
+/// Whether a table within a context is a shortcut, rather than a nested context.
+fn is_dest_table(t: &toml::value::Table) -> bool {
+    ["path", "container", "cmd", "last-visited"].iter().any(|key| t.contains_key(*key))
+}
+
+/// Process a context table into `contexts`. `path` is the context's path as written, and `key`
+/// the full key of the table, for error messages. Tables in it which don't describe shortcuts are
+/// nested contexts, for paths relative to this one. A "cd-cmd" key in it is the command to change
+/// to its shortcuts' directories with, for those that don't give their own; otherwise, it's
+/// `cd_cmd`, from the enclosing context.
+fn process_context(
+    config_file_path: &Path,
+    key: &str,
+    path: &str,
+    mut table: toml::value::Table,
+    relative_to: &Path,
+    cd_cmd: Option<&str>,
+    contexts: &mut BTreeMap<PathBuf, PathMapping>,
+) -> Result<(), Diagnostic> {
+    let invalid = |key: &str, msg: String| Diagnostic::new("config-invalid", msg).with_key(key);
+
+    let context_key = toml::Value::String(path.to_owned());
+    let context_path = match parse_toml_as_path(&context_key, relative_to) {
+        Ok(path) => path,
+        Err(msg) => { return Err(invalid(key, format!("error: {}", msg))); }
+    };
+
+    let cd_cmd = match table.remove(CD_CMD_KEY) {
+        Some(toml::Value::String(cmd)) => Some(cmd),
+        Some(other) => return Err(invalid(&format!("{}.{}", key, CD_CMD_KEY), format!(
+            "error at {:?}.{}: expected a string, not {}", context_path, CD_CMD_KEY,
+            other.type_str()))),
+        None => cd_cmd.map(str::to_owned),
+    };
+
+    let mut context_map = PathMapping::new();
+
+    for (name, path) in table {
+        let full_key = format!("{}.{}", key, name);
+        match path {
+            toml::Value::Table(t) if !is_dest_table(&t) => {
+                process_context(config_file_path, &full_key, &name, t, &context_path,
+                    cd_cmd.as_deref(), contexts)?;
+                continue;
+            }
+            _ => (),
+        }
+
+        let entry = match parse_toml_as_entry(
+            &path, &context_path, config_file_path, Some(&context_path))
+        {
+            Ok(entry) => entry,
+            Err(msg) => {
+                return Err(invalid(&full_key,
+                    format!("error at {:?}.{}: {}", context_path, name, msg)));
+            }
+        };
+
+        let entry = PathMappingEntry { cd_cmd: entry.cd_cmd.or_else(|| cd_cmd.clone()), ..entry };
+        context_map.insert(name, entry);
+    }
+
+    contexts.entry(context_path).or_default().extend(context_map);
+    Ok(())
+}
+
+/// Process the parsed configuration TOML into goto's configuration struct.
+/// All relative paths will be interpreted relative to `relative_to`.
+fn process_config(config_file_path: &Path, mut config_toml: toml::value::Table, relative_to: &Path)
+    -> Result<Configuration, Diagnostic>
+{
+    let invalid = |key: &str, msg: String| Diagnostic::new("config-invalid", msg).with_key(key);
+
+    apply_profile(&mut config_toml)?;
+    let host_table = take_host_table(&mut config_toml)?;
+
+    let version = config_version(&config_toml);
+    if version > CONFIG_VERSION {
+        return Err(invalid(VERSION_KEY, format!(
+            "version {} is newer than this version of goto supports ({})",
+            version, CONFIG_VERSION)));
+    }
+
+    let mut config = Configuration::default();
+
+    for (k, v) in config_toml {
+        match v {
+            toml::Value::Integer(_) if k == VERSION_KEY => (),
+            toml::Value::Table(t) if k == SETTINGS_KEY => {
+                config.settings = process_settings(&t, relative_to)?;
+            },
+            toml::Value::Table(t) if k == WORKSPACES_KEY => {
+                config.workspaces = process_workspaces(&t)?;
+            },
+            toml::Value::Table(t) if !is_entry_table(&t, version) => {
+                // A path context.
+
+                process_context(config_file_path, &k, &k, t, relative_to, None,
+                    &mut config.contexts)?;
+            },
+            v => {
+                // A top-level entry. Attempt to parse as a path and insert into the global table.
+                let entry = match parse_toml_as_entry(&v, relative_to, config_file_path, None) {
+                    Ok(entry) => entry,
+                    Err(msg) => {
+                        return Err(invalid(&k, format!(
+                            "error at {}: expected a table, a path string, or an array of paths, \
+                            not {} ({})",
+                             k, v.type_str(), msg)));
+                    },
+                };
+
+                config.global.insert(k, entry);
+            }
+        }
+    }
+
+    if let Some(mut host_table) = host_table {
+        // It's written the same way as the rest of the file.
+        host_table.insert(VERSION_KEY.to_owned(), toml::Value::Integer(version));
+        let mut overrides = process_config(config_file_path, host_table, relative_to)
+            .map_err(|diag| Diagnostic {
+                message: format!("in the table for this host: {}", diag.message),
+                ..diag
+            })?;
+        if overrides.host_overrides.is_some() {
+            return Err(invalid(HOSTS_KEY, "host tables can't have host tables in them".to_owned()));
+        }
+        inherit_entry_details(&mut overrides.global, &config.global);
+        for (context_path, mapping) in overrides.contexts.iter_mut() {
+            if let Some(originals) = config.contexts.get(context_path) {
+                inherit_entry_details(mapping, originals);
+            }
+        }
+        config.host_overrides = Some(Box::new(overrides));
+    }
+
+    Ok(config)
+}
+
+/// Combine two sets of entries. The entries in `overlay` take precedence, unless the entry they
+/// would replace has a higher priority.
+fn combine_mappings(combined: &mut PathMapping, overlay: PathMapping) {
+    for (name, entry) in overlay {
+        match combined.entry(name) {
+            Entry::Occupied(mut existing) => {
+                if entry.priority >= existing.get().priority {
+                    existing.insert(entry);
+                }
+            },
+            Entry::Vacant(vacant) => {
+                vacant.insert(entry);
+            }
+        }
+    }
+}
+
+/// Combine two configurations. The entries in `overlay` take precedence, except over those with a
+/// higher priority.
+fn combine_configs(combined: &mut Configuration, overlay: Configuration) {
+    combine_mappings(&mut combined.global, overlay.global);
+    combine_mappings(&mut combined.temporary, overlay.temporary);
+    combine_settings(&mut combined.settings, overlay.settings);
+    combined.workspaces.extend(overlay.workspaces);
+    for (context_path, context) in overlay.contexts {
+        match combined.contexts.entry(context_path) {
+            Entry::Occupied(mut combined_context) => {
+                combine_mappings(combined_context.get_mut(), context);
+            },
+            Entry::Vacant(entry) => {
+                entry.insert(context);
+            }
+        }
+    }
+}
+
+/// Process the configuration file at the given path, given the result of reading its text.
+/// If the file does not exist, returns Ok(None), otherwise if the file cannot be read or processed
+/// for any reason, returns a diagnostic explaining the error.
+fn read_config(config_path: &Path, config_text: io::Result<String>)
+    -> Result<Option<Configuration>, Diagnostic>
+{
+    let config_toml = match config_text.and_then(|text| parse_config_text(config_path, &text)) {
+        Ok(toml) => toml,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            let kind = if e.kind() == io::ErrorKind::InvalidData {
+                "config-syntax"
+            } else {
+                "config-read"
+            };
+            let msg = format!("failed to read configuration {:?}: {}", config_path, e);
+            return Err(Diagnostic::new(kind, msg).with_file(config_path));
+        }
+    };
+
+    timing::time(|| format!("parse {:?}", config_path),
+        || process_config(config_path, config_toml, config_path.parent().unwrap()))
+        .map_err(|diag| Diagnostic {
+            message: format!("invalid configuration in {:?}: {}", config_path, diag.message),
+            ..diag.with_file(config_path)
+        })
+        .map(Some)
+}
+
+/// Whether two paths refer to the same file or directory.
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Whether two paths refer to the same file or directory.
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// The current directory as the shell sees it, which may be through symbolic links, unlike what
+/// the OS reports. $PWD is only trusted if it really is the same directory.
+fn logical_cwd(physical: PathBuf) -> PathBuf {
+    match env::var_os("PWD").map(PathBuf::from) {
+        Some(pwd) if pwd.is_absolute() && same_file(&pwd, &physical) => pwd,
+        _ => physical,
+    }
+}
+
+/// The files and directories whose presence marks the directory the built-in "root" shortcut
+/// leads to, unless the "root-markers" setting gives others.
+const DEFAULT_ROOT_MARKERS: &[&str] = &[".git", ".hg", "Cargo.toml", "package.json"];
+
+/// A configuration layer defining just the built-in "root" shortcut, to `dest`. `source_file` is
+/// what it's shown as coming from.
+fn root_layer(dest: Destination, source_file: PathBuf, desc: Option<String>) -> Configuration {
+    let mut builtin = Configuration::default();
+    builtin.global.insert("root".to_owned(),
+        PathMappingEntry { desc, ..PathMappingEntry::new(dest, source_file) });
+    builtin
+}
+
+/// Find the root of the git repository (or worktree) containing `path`, if any.
+fn find_repo_root(path: &Path) -> Option<&Path> {
+    let found = in_each_dir(path.ancestors().map(Path::to_owned).collect(),
+        |dir| dir.join(".git").exists());
+    let i = found.into_iter().position(|found| found == Some(true))?;
+    path.ancestors().nth(i)
+}
+
+/// Move the top-level entries of a configuration into a context for the given path, so that they
+/// take precedence the way context entries do. Entries already in that context win.
+fn scope_globals_to_context(config: &mut Configuration, context_path: &Path) {
+    let mut globals = std::mem::take(&mut config.global);
+    for entry in globals.values_mut() {
+        entry.context = Some(context_path.to_owned());
+    }
+    let context = config.contexts.entry(context_path.to_owned()).or_default();
+    globals.append(context);
+    *context = globals;
+}
+
+/// The paths of all the configuration files which may apply in `cwd`, whether they exist or not,
+/// in increasing order of precedence: from the root of the filesystem up to `cwd`, and finally the
+/// user's home configuration.
+fn config_file_paths(home_config_path: &Path, cwd: &Path) -> Vec<PathBuf> {
+    assert!(cwd.is_absolute());
+
+    // Walk from the root up to `cwd`. The home configuration is skipped if it's on the way, since
+    // it comes last regardless.
+    let mut dirs = cwd.ancestors().map(Path::to_owned).collect::<Vec<_>>();
+    dirs.reverse();
+    let mut paths = in_each_dir(dirs, find_config_file)
+        .into_iter()
+        .flatten()
+        .filter(|path| path != home_config_path)
+        .collect::<Vec<_>>();
+    paths.push(home_config_path.to_owned());
+    paths
+}
+
+/// How long to wait for the configuration files in a directory to be found and read, before
+/// giving up on it. Directories on network filesystems whose server has gone away can otherwise
+/// block forever.
+const DIR_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Directories which didn't respond within `DIR_TIMEOUT`, and haven't since, so they're skipped
+/// without waiting on them again.
+static UNREACHABLE: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Run `f` on each of `places` (directories, mostly) at once, each in a thread of its own, and
+/// return what it returns for each, in the same order. Everything which looks in the directories
+/// above the current one goes through this, because on network filesystems, waiting for them one
+/// after another adds up, and those whose server has gone away can block forever.
+///
+/// Places which take longer than `DIR_TIMEOUT` are skipped, with a warning, and come out as None.
+/// Their threads are left behind, stuck, until they finish or goto exits.
+fn in_each_dir<T: Send + 'static>(
+    places: Vec<PathBuf>,
+    f: impl Fn(&Path) -> T + Send + Sync + 'static,
+) -> Vec<Option<T>> {
+    let f = Arc::new(f);
+    let receivers = places.into_iter()
+        .map(|place| {
+            if UNREACHABLE.lock().unwrap().contains(&place) {
+                return (place, None);
+            }
+            let (sender, receiver) = mpsc::channel();
+            let (f, thread_place) = (Arc::clone(&f), place.clone());
+            std::thread::spawn(move || {
+                let result = f(&thread_place);
+                UNREACHABLE.lock().unwrap().remove(&thread_place);
+                // Nobody is listening if this took too long.
+                let _ = sender.send(result);
+            });
+            (place, Some(receiver))
+        })
+        .collect::<Vec<_>>();
+
+    // They run at the same time, so they can share a deadline.
+    let deadline = Instant::now() + DIR_TIMEOUT;
+    receivers.into_iter()
+        .map(|(place, receiver)| {
+            let result = receiver?.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+            if result.is_err() {
+                let msg = format!("skipping {:?}, which didn't respond within {} seconds",
+                    place, DIR_TIMEOUT.as_secs());
+                report(&Diagnostic::new("unreachable", msg)
+                    .with_file(&place)
+                    .with_severity(Severity::Warning));
+                UNREACHABLE.lock().unwrap().insert(place);
+            }
+            result.ok()
+        })
+        .collect()
+}
+
+/// Which of `paths` are known not to exist. They're looked at all at once, through `in_each_dir`,
+/// and those which don't respond, or are under places which didn't before, are left out: whether
+/// they exist isn't known.
+fn missing_paths(paths: Vec<PathBuf>) -> BTreeSet<PathBuf> {
+    let paths = paths.into_iter()
+        .filter(|path| !UNREACHABLE.lock().unwrap().iter().any(|place| path.starts_with(place)))
+        .collect::<Vec<_>>();
+    let exists = in_each_dir(paths.clone(), |path| path.exists());
+    paths.into_iter()
+        .zip(exists)
+        .filter(|(_, exists)| *exists == Some(false))
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Find and read the configuration files which may apply in `cwd`, the ones `config_file_paths`
+/// lists, along with the result of reading each. Directories which don't respond in time are
+/// skipped, as if they had no configuration file.
+fn read_config_files(home_config_path: &Path, cwd: &Path) -> daemon::ConfigFiles {
+    assert!(cwd.is_absolute());
+
+    // As in `config_file_paths`, the home configuration comes last, even if it's on the way.
+    let mut places = cwd.ancestors().map(Path::to_owned).collect::<Vec<_>>();
+    places.reverse();
+    places.push(home_config_path.to_owned());
+    let home = home_config_path.to_owned();
+    let read = move |place: &Path| {
+        // The home configuration is read from where it's known to be; in the other directories,
+        // it has to be found first.
+        let path = if place == home {
+            home.clone()
+        } else {
+            timing::time(|| format!("find the configuration file for {:?}", place),
+                || find_config_file(place))
+        };
+        let text = timing::time(|| format!("read {:?}", path), || read_config_text(&path));
+        (path, text)
+    };
+    let home_index = places.len() - 1;
+    in_each_dir(places, read)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, file)| {
+            file.filter(|(path, _)| i == home_index || path != home_config_path)
+        })
+        .collect()
+}
+
+/// Read all configuration files for a given path, by walking up the directory stack from the root
+/// to `cwd`, and finally the user's home configuration. They are returned separately, in
+/// increasing order of precedence. If reading any of them fails (other than because the file does
+/// not exist), returns an appropriate diagnostic.
+///
+/// If `repo_root` is given, the top-level entries of the configuration there are scoped to it as
+/// a context, and a "root" entry pointing at it is added at the lowest precedence. Otherwise the
+/// "root" entry points at the nearest directory with a root marker in it, if there is one.
+fn read_config_layers(home_config_path: &Path, cwd: &Path, repo_root: Option<&Path>)
+    -> Result<Vec<Configuration>, Diagnostic>
+{
+    let files = match fetch_from_daemon(home_config_path, cwd) {
+        Some(files) => files,
+        None => parse_config_files(read_config_files_timed(home_config_path, cwd)),
+    };
+    config_layers(home_config_path, cwd, repo_root, files)
+}
+
+/// Like `read_config_layers`, but for looking up only `name`: the files which can't change where
+/// it leads aren't parsed, if those which can settle it.
+///
+/// A file can only change that if it defines something by that name, in any context, or if it has
+/// settings, which can change how names are matched and how goto goes to them. So files whose text
+/// doesn't mention either (ignoring case and Unicode normalization form, in case the settings
+/// allow for those) are left out, as long as what's left defines `name` without leading to another
+/// shortcut, which could be defined in any of them. Otherwise, all of them are parsed after all.
+/// The files left out aren't checked for errors either. If the daemon is running, it has parsed
+/// them all already.
+fn read_config_layers_for(home_config_path: &Path, cwd: &Path, repo_root: Option<&Path>,
+    name: &str) -> Result<Vec<Configuration>, Diagnostic>
+{
+    if let Some(files) = fetch_from_daemon(home_config_path, cwd) {
+        return config_layers(home_config_path, cwd, repo_root, files);
+    }
+    let files = read_config_files_timed(home_config_path, cwd);
+    let fold = |text: &str| text.nfc().collect::<String>().to_lowercase();
+    let folded_name = fold(name);
+    let bearing = files.iter()
+        .filter_map(|(path, text)| match text {
+            Ok(text) if path == home_config_path || {
+                let text = fold(text);
+                text.contains(&folded_name) || text.contains(SETTINGS_KEY)
+            } => Some((path.clone(), Ok(text.clone()))),
+            Ok(_) => None,
+            // Errors (other than there being no file) are reported just the same.
+            Err(e) => Some((path.clone(), Err(io::Error::new(e.kind(), e.to_string())))),
+        })
+        .collect::<Vec<_>>();
+    if bearing.len() < files.len() {
+        let count = bearing.iter().filter(|(_, text)| text.is_ok()).count();
+        let layers = timing::time(|| format!("parse the {} configuration files which bear on {:?}",
+            count, name),
+            || config_layers(home_config_path, cwd, repo_root, parse_config_files(bearing)));
+        let settled = layers.as_ref().is_ok_and(|layers| {
+            lookup(&combine_layers(layers), cwd, name)
+                .is_some_and(|entry| !entry.dest.refers_to_shortcuts())
+        });
+        if settled {
+            return layers;
+        }
+    }
+    config_layers(home_config_path, cwd, repo_root, parse_config_files(files))
+}
+
+/// A configuration file, with what it says, or what's wrong with it.
+type ParsedFile = (PathBuf, Result<Configuration, Diagnostic>);
+
+/// Get the configuration files which may apply in `cwd`, already parsed, from the daemon, if it's
+/// running.
+fn fetch_from_daemon(home_config_path: &Path, cwd: &Path) -> Option<Vec<ParsedFile>> {
+    timing::time(|| "ask the daemon for the configuration files".to_owned(),
+        || daemon::fetch_configs(home_config_path, cwd))
+}
+
+fn read_config_files_timed(home_config_path: &Path, cwd: &Path) -> daemon::ConfigFiles {
+    timing::time(|| format!("find and read the configuration files for {:?}", cwd),
+        || read_config_files(home_config_path, cwd))
+}
+
+/// Parse the configuration files which were read, leaving out those which don't exist.
+fn parse_config_files(files: daemon::ConfigFiles) -> Vec<ParsedFile> {
+    files.into_iter()
+        .filter_map(|(path, text)| {
+            let config = read_config(&path, text).transpose()?;
+            Some((path, config))
+        })
+        .collect()
+}
+
+/// Turn the configuration files which may apply in `cwd`, in increasing order of precedence, into
+/// the layers `read_config_layers` returns.
+fn config_layers(home_config_path: &Path, cwd: &Path, repo_root: Option<&Path>,
+    files: Vec<ParsedFile>) -> Result<Vec<Configuration>, Diagnostic>
+{
+    let mut layers = vec![];
+
+    if let Some(root) = repo_root {
+        layers.push(root_layer(Destination::Local(root.to_owned()), root.join(".git"),
+            Some("the root of the git repository".to_owned())));
+    }
+
+    // Overrides for this machine, from any of the files, come above all of them.
+    let mut host_layers = vec![];
+
+    let builtin_layers = layers.len();
+    for (toml_path, config) in files {
+        let mut config = config?;
+        let mut host = config.host_overrides.take().map(|host| *host);
+        if toml_path != home_config_path {
+            reject_commands(&config, &toml_path)?;
+            if let Some(host) = &host {
+                reject_commands(host, &toml_path)?;
+            }
+        }
+        if let (Some(root), Some(dir)) = (repo_root, toml_path.parent()) {
+            if toml_path != home_config_path && root == dir {
+                scope_globals_to_context(&mut config, root);
+                if let Some(host) = &mut host {
+                    scope_globals_to_context(host, root);
+                }
+            }
+        }
+        host_layers.extend(host);
+        if toml_path == home_config_path && config.settings.home_lowest == Some(true) {
+            layers.insert(builtin_layers, config);
+        } else {
+            layers.push(config);
+        }
+    }
+
+    // Overlays named by the files just read, or by $GOTO_OVERLAYS, come below all of them.
+    let mut overlay_paths: Vec<PathBuf> = env::var_os(OVERLAYS_VAR)
+        .map(|paths| env::split_paths(&paths).filter(|path| path.is_absolute()).collect())
+        .unwrap_or_default();
+    for layer in &layers {
+        for path in layer.settings.overlays.iter().flatten() {
+            if !overlay_paths.contains(path) {
+                overlay_paths.push(path.clone());
+            }
+        }
+    }
+    let mut overlays = vec![];
+    for path in overlay_paths {
+        let text = timing::time(|| format!("read {:?}", path), || read_config_text(&path));
+        if let Some(mut config) = read_config(&path, text)? {
+            reject_commands(&config, &path)?;
+            if let Some(host) = config.host_overrides.take() {
+                reject_commands(&host, &path)?;
+                host_layers.insert(0, *host);
+            }
+            overlays.push(config);
+        }
+    }
+    layers.splice(builtin_layers .. builtin_layers, overlays);
+
+    // Without --git, "root" is the nearest directory with one of the root markers in it, which is
+    // only looked for if it's used.
+    if repo_root.is_none() {
+        let markers = layers.iter().rev()
+            .find_map(|layer| layer.settings.root_markers.clone())
+            .unwrap_or_else(|| DEFAULT_ROOT_MARKERS.iter().map(|&m| m.to_owned()).collect());
+        let dest = Destination::NearestRoot { from: cwd.to_owned(), markers };
+        layers.insert(0, root_layer(dest, cwd.to_owned(), None));
+    }
+
+    layers.extend(host_layers);
+
+    // Temporary shortcuts, from 'goto add --temp', come above everything else.
+    layers.extend(temp::layer());
+
+    Ok(layers)
+}
+
+/// Check that a configuration doesn't define any command destinations or mount commands. Those
+/// run arbitrary commands, so they're only allowed in the home configuration, which the user wrote
+/// themselves, and not in files that came along with a directory.
+fn reject_commands(config: &Configuration, config_path: &Path) -> Result<(), Diagnostic> {
+    let all_entries = config.global.iter()
+        .chain(config.contexts.values().flat_map(|mapping| mapping.iter()));
+    for (name, entry) in all_entries {
+        if entry.dest.runs_command() {
+            let msg = format!("{:?} defines {:?} as a command, which is only allowed in the home \
+                configuration", config_path, name);
+            return Err(Diagnostic::new("untrusted-command", msg)
+                .with_file(config_path)
+                .with_key(name));
+        }
+        if entry.mount.is_some() {
+            let msg = format!("{:?} gives {:?} a mount command, which is only allowed in the home \
+                configuration", config_path, name);
+            return Err(Diagnostic::new("untrusted-command", msg)
+                .with_file(config_path)
+                .with_key(name));
+        }
+    }
+    Ok(())
+}
+
+/// Combine configuration layers, in increasing order of precedence, into one.
+fn combine_layers(layers: &[Configuration]) -> Configuration {
+    timing::time(|| format!("merge {} configuration layers", layers.len()), || {
+        let mut combined = Configuration::default();
+        for layer in layers {
+            combine_configs(&mut combined, layer.clone());
+        }
+        combined
+    })
+}
+
+/// Read and combine all configuration files for a given path. See `read_config_layers`. The
+/// combined 'messages' setting applies to the messages reported from then on.
+fn read_combine_configs(home_config_path: &Path, cwd: &Path, repo_root: Option<&Path>)
+    -> Result<Configuration, Diagnostic>
+{
+    read_config_layers(home_config_path, cwd, repo_root).map(|layers| {
+        let config = combine_layers(&layers);
+        output::set_templates(config.settings.messages.clone().unwrap_or_default());
+        config
+    })
+}
+
+/// Get the contexts which apply in `cwd`, most specific (i.e. longest path) first.
+fn matching_contexts<'a>(config: &'a Configuration, cwd: &Path)
+    -> Vec<(&'a PathBuf, &'a PathMapping)>
+{
+    let mut matching: Vec<_> = config.contexts.iter()
+        .filter(|(context_path, _)| context_applies(context_path, cwd, &config.settings))
+        .collect();
+    matching.sort_by_key(|(context_path, _)| std::cmp::Reverse(context_path.as_os_str().len()));
+    matching
+}
+
+/// Look up a shortcut by name, as seen from `cwd`.
+///
+/// Contexts can have keys that overlap with other contexts. The rule is that the longest context
+/// path that matches the CWD takes precedence, and any context takes precedence over the global
+/// entries. Temporary shortcuts take precedence over all of them.
+///
+/// Within each of those, an exact match of the name is preferred, followed by one differing only in
+/// case or normalization form if the "name-case" and "normalize-unicode" settings allow it.
+fn lookup<'a>(config: &'a Configuration, cwd: &Path, name: &str) -> Option<&'a PathMappingEntry> {
+    find_name(&config.temporary, name, &config.settings)
+        .or_else(|| {
+            matching_contexts(config, cwd)
+                .into_iter()
+                .find_map(|(_, map)| find_name(map, name, &config.settings))
+        })
+        .or_else(|| find_name(&config.global, name, &config.settings))
+        .filter(|entry| !matches!(entry.dest, Destination::Disabled))
+}
+
+/// How many aliases can lead to one another before giving up.
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// Settle where a destination leads, now that it's being used: follow an alias for another
+/// shortcut, and any further aliases that one leads to, to where they end up, and pick the first
+/// of a list of alternatives which exists, the newest directory in one, the directory last visited,
+/// or the nearest root. Other destinations, including commands, which are only run with
+/// `run_dest_command`, are returned as they are.
+fn resolve_dest(config: &Configuration, cwd: &Path, dest: &Destination)
+    -> Result<Destination, Diagnostic>
+{
+    let mut dest = dest.clone();
+    let mut chain: Vec<String> = vec![];
+    loop {
+        dest = match dest {
+            Destination::Alias(name) => {
+                if chain.contains(&name) || chain.len() >= MAX_ALIAS_DEPTH {
+                    chain.push(name.clone());
+                    let msg = format!("shortcuts lead to each other in a loop: {}",
+                        chain.join(" → "));
+                    return Err(Diagnostic::new("alias-loop", msg).with_key(name));
+                }
+                chain.push(name.clone());
+                match lookup(config, cwd, &name) {
+                    Some(entry) => entry.dest.clone(),
+                    None => {
+                        let msg = format!("@{} refers to a shortcut which isn't defined here",
+                            name);
+                        return Err(Diagnostic::new("alias-missing", msg).with_key(name));
+                    }
+                }
+            }
+            Destination::Alternatives(mut alternatives) => {
+                let found = alternatives.iter()
+                    .position(|dest| !matches!(dest, Destination::Local(path) if !path.exists()));
+                // If none of them exist, the first one is as good as any for reporting that.
+                alternatives.swap_remove(found.unwrap_or(0))
+            }
+            Destination::NewestChild(dest) => match resolve_dest(config, cwd, &dest)? {
+                Destination::Local(path) => {
+                    Destination::Local(newest_child(&path).unwrap_or(path))
+                }
+                dest => dest,
+            },
+            Destination::LastVisited { under, fallback } => {
+                match resolve_dest(config, cwd, &fallback)? {
+                    Destination::Local(path) => {
+                        Destination::Local(history::last_visited_under(&under).unwrap_or(path))
+                    }
+                    dest => dest,
+                }
+            }
+            Destination::NearestRoot { from, markers } => {
+                let msg = format!("no directory here or above has {} in it", markers.join(" or "));
+                let found = in_each_dir(from.ancestors().map(Path::to_owned).collect(),
+                    move |dir| markers.iter().any(|marker| dir.join(marker).exists()));
+                match found.into_iter().position(|found| found == Some(true)) {
+                    Some(i) => Destination::Local(from.ancestors().nth(i).unwrap().to_owned()),
+                    None => return Err(Diagnostic::new("not-found", msg).with_key("root")),
+                }
+            }
+            dest => return Ok(dest),
+        };
+    }
+}
+
+/// Find every definition of `name` which applies in `cwd`, across all configuration layers, in
+/// order of precedence. The first one is what `lookup` finds in the combined configuration.
+fn all_definitions<'a>(layers: &'a [Configuration], cwd: &Path, name: &str, settings: &Settings)
+    -> Vec<&'a PathMappingEntry>
+{
+    let mut context_paths = layers.iter()
+        .flat_map(|layer| layer.contexts.keys())
+        .filter(|context_path| context_applies(context_path, cwd, settings))
+        .collect::<Vec<_>>();
+    context_paths.sort_by(|a, b| {
+        b.as_os_str().len().cmp(&a.as_os_str().len()).then_with(|| a.cmp(b))
+    });
+    context_paths.dedup();
+
+    // Within each context, and within the global entries, definitions from later layers come
+    // first, but higher priorities come before that.
+    let in_order = |mut group: Vec<&'a PathMappingEntry>| {
+        group.reverse();
+        group.sort_by_key(|entry| std::cmp::Reverse(entry.priority));
+        group
+    };
+
+    // Temporary shortcuts come first, whatever the context.
+    let mut found = in_order(layers.iter()
+        .filter_map(|layer| find_name(&layer.temporary, name, settings))
+        .collect());
+    for context_path in context_paths {
+        found.extend(in_order(layers.iter()
+            .filter_map(|layer| {
+                layer.contexts.get(context_path).and_then(|map| find_name(map, name, settings))
+            })
+            .collect()));
+    }
+    found.extend(in_order(layers.iter()
+        .filter_map(|layer| find_name(&layer.global, name, settings))
+        .collect()));
+    // A disabled definition hides all those after it.
+    if let Some(disabled) = found.iter().position(|e| matches!(e.dest, Destination::Disabled)) {
+        found.truncate(disabled);
+    }
+    found
+}
+
+/// The name an entry is defined with in its layer.
+fn defined_name<'a>(layers: &'a [Configuration], entry: &PathMappingEntry) -> Option<&'a str> {
+    layers.iter()
+        .flat_map(|layer| {
+            layer.temporary.iter().chain(&layer.global).chain(layer.contexts.values().flatten())
+        })
+        .find(|(_, other)| std::ptr::eq(*other, entry))
+        .map(|(name, _)| name.as_str())
+}
+
+/// Find the keys leading to the table in a configuration file's contents which defines `name` in
+/// the context at `context`, where `table` holds contexts and shortcuts relative to `relative_to`,
+/// and `is_context` tells which tables in it are contexts.
+fn context_table_keys(
+    table: &toml::value::Table,
+    is_context: &dyn Fn(&toml::value::Table) -> bool,
+    relative_to: &Path,
+    context: &Path,
+    name: &str,
+) -> Option<Vec<String>> {
+    for (key, value) in table {
+        let toml::Value::Table(t) = value else {
+            continue;
+        };
+        let reserved = [SETTINGS_KEY, PROFILES_KEY, HOSTS_KEY, WORKSPACES_KEY];
+        if reserved.contains(&key.as_str()) || !is_context(t) {
+            continue;
+        }
+        let path = parse_toml_as_path(&toml::Value::String(key.clone()), relative_to).ok()?;
+        if path == context && t.contains_key(name) {
+            return Some(vec![key.clone()]);
+        }
+        let is_nested_context = |t: &toml::value::Table| !is_dest_table(t);
+        if let Some(mut keys) = context_table_keys(t, &is_nested_context, &path, context, name) {
+            keys.insert(0, key.clone());
+            return Some(keys);
+        }
+    }
+    None
+}
+
+/// Delete the definition of `name` in `context` (or at the top level) from a configuration file,
+/// or with `new_name`, rename it.
+fn edit_definition(path: &Path, context: Option<&Path>, name: &str, new_name: Option<&str>)
+    -> Result<(), String>
+{
+    let dir = path.parent().unwrap();
+    edit::edit_config(path, |config_toml| {
+        let not_found = || format!("couldn't find the definition of {} in {:?}", name, path);
+        let keys = match context {
+            None => vec![],
+            Some(context) => {
+                let version = config_version(config_toml);
+                let is_context = |t: &toml::value::Table| !is_entry_table(t, version);
+                context_table_keys(config_toml, &is_context, dir, context, name)
+                    .ok_or_else(not_found)?
+            }
+        };
+        let mut table = config_toml;
+        for key in keys {
+            match table.get_mut(&key) {
+                Some(toml::Value::Table(t)) => table = t,
+                _ => return Err(not_found()),
+            }
+        }
+        if new_name.is_some_and(|new_name| table.contains_key(new_name)) {
+            return Err(format!("{} is already defined there", new_name.unwrap()));
+        }
+        let value = table.remove(name).ok_or_else(not_found)?;
+        if let Some(new_name) = new_name {
+            table.insert(new_name.to_owned(), value);
+        }
+        Ok(true)
+    })
+}
+
+/// List the names with more than one definition in effect in `cwd`, with all the definitions, the
+/// one used first. With `edit`, offer to delete or rename each of the others, where they're in
+/// one of the `editable` files.
+fn conflicts(
+    layers: &[Configuration],
+    cwd: &Path,
+    settings: &Settings,
+    editable: &[PathBuf],
+    edit: bool,
+) -> Result<(), String> {
+    let names = layers.iter()
+        .flat_map(|layer| {
+            layer.temporary.keys().chain(layer.global.keys())
+                .chain(layer.contexts.values().flat_map(|m| m.keys()))
+        })
+        .collect::<BTreeSet<_>>();
+    let formatter = list::ListFormatter::new();
+    let mut seen = vec![];
+    for name in names {
+        let definitions = all_definitions(layers, cwd, name, settings);
+        if definitions.len() < 2 || seen.iter().any(|&e| std::ptr::eq(e, definitions[0])) {
+            continue;
+        }
+        seen.push(definitions[0]);
+        output::print("conflict", format!("{}:", name));
+        for (i, entry) in definitions.iter().enumerate() {
+            let marker = if i == 0 { "*" } else { " " };
+            let name = defined_name(layers, entry).unwrap_or(name);
+            output::print("conflict",
+                format!("  {} {}", marker, formatter.format_entry(name, entry)));
+        }
+        if !edit {
+            continue;
+        }
+        for entry in &definitions[1 ..] {
+            let name = defined_name(layers, entry).unwrap_or(name);
+            if !editable.contains(&entry.source_file) {
+                continue;
+            }
+            let question = format!("{} in {:?}: [k]eep, [d]elete, or [r]ename it? [K/d/r]",
+                name, entry.source_file);
+            let result = match prompt(&question).as_str() {
+                "d" | "D" => edit_definition(&entry.source_file, entry.context.as_deref(), name,
+                    None).map(|()| {
+                        output::note(format!("deleted {} from {:?}", name, entry.source_file))
+                    }),
+                "r" | "R" => match prompt("New name:").as_str() {
+                    "" => Ok(()),
+                    new_name => edit_definition(&entry.source_file, entry.context.as_deref(),
+                        name, Some(new_name))
+                        .map(|()| output::note(format!("renamed {} to {} in {:?}", name, new_name,
+                            entry.source_file))),
+                },
+                _ => Ok(()),
+            };
+            if let Err(msg) = result {
+                report(&Diagnostic::from(msg));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Ask which of several choices to take, returning its index. This uses stderr and stdin, since
+/// stdout goes to the shell.
+fn choose(choices: &[String]) -> Result<usize, String> {
+    for (i, choice) in choices.iter().enumerate() {
+        output::print("choice", format!("{}) {}", i + 1, choice));
+    }
+    output::ask("which one? [1]");
+    let mut line = String::new();
+    io::stdin().read_line(&mut line).map_err(|e| format!("failed to read choice: {}", e))?;
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(0);
+    }
+    line.parse::<usize>().ok()
+        .and_then(|n| n.checked_sub(1))
+        .filter(|&i| i < choices.len())
+        .ok_or_else(|| format!("invalid choice {:?}", line))
+}
+
+/// Get all the shortcuts in effect in `cwd`, following the same precedence rules as `lookup`.
+fn effective_mapping(config: &Configuration, cwd: &Path) -> PathMapping {
+    let mut effective_map = PathMapping::new();
+    let maps = std::iter::once(&config.temporary)
+        .chain(matching_contexts(config, cwd).into_iter().map(|(_, map)| map))
+        .chain(std::iter::once(&config.global));
+    for map in maps {
+        for (k, v) in map {
+            if let Entry::Vacant(entry) = effective_map.entry(k.clone()) {
+                entry.insert(v.clone());
+            }
+        }
+    }
+    effective_map.retain(|_, v| !matches!(v.dest, Destination::Disabled));
+    effective_map
+}
+
+/// Find the shortcuts in effect in `cwd` whose destinations are `path` or one of its ancestors,
+/// along with the rest of the path below the destination. The closest ones come first.
+fn reverse_lookup(config: &Configuration, cwd: &Path, path: &Path) -> Vec<(String, PathBuf)> {
+    let mut found = effective_mapping(config, cwd)
+        .into_iter()
+        .filter_map(|(name, entry)| match entry.dest {
+            Destination::Local(dest) => {
+                let rest = path.strip_prefix(&dest).ok()?.to_owned();
+                Some((name, rest))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    found.sort_by(|(name_a, rest_a), (name_b, rest_b)| {
+        rest_a.components().count().cmp(&rest_b.components().count())
+            .then_with(|| name_a.cmp(name_b))
+    });
+    found
+}
+
+/// Print what to type to get to `path` from `cwd`, for each shortcut that leads there.
+fn name_of(config: &Configuration, cwd: &Path, path: &Path) -> Result<(), String> {
+    let found = reverse_lookup(config, cwd, path);
+    if found.is_empty() {
+        return Err(format!("no shortcut leads to {:?}", path));
+    }
+    for (name, rest) in found {
+        if rest.as_os_str().is_empty() {
+            output::print("name-of", format!("goto {}", name));
+        } else {
+            output::print("name-of", format!("goto {} {}", name, rest.display()));
+        }
+    }
+    Ok(())
+}
+
+/// Describe `cwd` compactly as the closest shortcut leading to it plus the rest of the path.
+/// The default ("*") shortcuts are skipped, since their name says nothing about where they go.
+fn breadcrumb(config: &Configuration, cwd: &Path) -> Option<String> {
+    let (name, rest) = reverse_lookup(config, cwd, cwd)
+        .into_iter()
+        .find(|(name, _)| name != "*")?;
+    if rest.as_os_str().is_empty() {
+        Some(name)
+    } else {
+        Some(format!("{}/{}", name, rest.display()))
+    }
+}
+
+/// Print the jumps from the history, for `goto history`.
+fn print_history(jumps: &[history::Jump], format: OutputFormat) {
+    for jump in jumps {
+        let fields = [jump.time.to_string(), jump.name.clone(), jump.dest.clone(),
+            jump.cwd.clone()];
+        match format {
+            OutputFormat::Text if jump.name.is_empty() => output::print("history",
+                format!("{}  {} (from {})", history::format_time(jump.time), jump.dest, jump.cwd)),
+            OutputFormat::Text => output::print("history", format!("{}  {} → {} (from {})",
+                history::format_time(jump.time), jump.name, jump.dest, jump.cwd)),
+            OutputFormat::Json => println!("{}", serde_json::json!({
+                "time": jump.time,
+                "name": Some(&jump.name).filter(|name| !name.is_empty()),
+                "dest": jump.dest,
+                "cwd": jump.cwd,
+            })),
+            OutputFormat::Tsv => println!("{}", list::tsv_record(&fields)),
+            OutputFormat::Csv => println!("{}", list::csv_record(&fields)),
+        }
+    }
+}
+
+/// Make a symbolic link to a directory.
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, link);
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_dir(target, link);
+}
+
+/// Make a symbolic link in `dir` for each local shortcut in effect in `cwd`, named after it, for
+/// programs which can't use goto. Links which already lead to the right place are left alone,
+/// and others are replaced, but only if they're links. With `clean`, other links in `dir` are
+/// removed.
+fn link_farm(config: &Configuration, cwd: &Path, dir: &Path, clean: bool) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("failed to create {:?}: {}", dir, e))?;
+    let mut linked = vec![];
+    for (name, entry) in effective_mapping(config, cwd) {
+        // The default shortcut has no name to give a link, and others may not make file names.
+        if name == "*" || name.contains(['/', '\\']) || name.starts_with('.') {
+            continue;
+        }
+        let Ok(Destination::Local(target)) = resolve_dest(config, cwd, &entry.dest) else {
+            continue;
+        };
+        let link = dir.join(&name);
+        match link.symlink_metadata() {
+            Ok(meta) if meta.is_symlink() => {
+                if link.read_link().ok().as_ref() == Some(&target) {
+                    linked.push(name);
+                    continue;
+                }
+                std::fs::remove_file(&link)
+                    .map_err(|e| format!("failed to remove {:?}: {}", link, e))?;
+            }
+            Ok(_) => {
+                output::warn("skipped", format!("skipping {:?}, which isn't a link", link));
+                continue;
+            }
+            Err(_) => (),
+        }
+        symlink_dir(&target, &link).map_err(|e| format!("failed to link {:?}: {}", link, e))?;
+        output::note(format!("{} → {:?}", name, target));
+        linked.push(name);
+    }
+
+    if clean {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("failed to read directory {:?}: {}", dir, e))?;
+        for entry in entries.flatten() {
+            let is_link = entry.file_type().is_ok_and(|t| t.is_symlink());
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if is_link && !linked.contains(&name) {
+                std::fs::remove_file(entry.path())
+                    .map_err(|e| format!("failed to remove {:?}: {}", entry.path(), e))?;
+                output::note(format!("removed {}", name));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Turn a shortcut name into an environment variable name, with the given prefix.
+fn env_var_name(prefix: &str, name: &str) -> String {
+    let mut var = format!("{}{}", prefix, name)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect::<String>();
+    if var.starts_with(|c: char| c.is_ascii_digit()) {
+        var.insert(0, '_');
+    }
+    var
+}
+
+/// Print a command exporting a variable for each local shortcut in effect in `cwd`. The default
+/// ("*") shortcuts are skipped, like command destinations, which would have to be run.
+fn print_env(config: &Configuration, cwd: &Path, prefix: &str) {
+    for (name, entry) in effective_mapping(config, cwd) {
+        if name == "*" {
+            continue;
+        }
+        if let Ok(Destination::Local(path)) = resolve_dest(config, cwd, &entry.dest) {
+            let var = env_var_name(prefix, &name);
+            println!("export {}={}", var, shell_quote(&path.to_string_lossy()));
+        }
+    }
+}
+
+/// Print a value for CDPATH: the destinations of the search roots if there are any, or the parents
+/// of all the local destinations otherwise, in order of the shortcuts' names, without repeats.
+fn print_cdpath(config: &Configuration, cwd: &Path) -> Result<(), String> {
+    let mut dirs = Vec::<PathBuf>::new();
+    if let Some(roots) = &config.settings.search_roots {
+        for root in roots {
+            let dest = lookup(config, cwd, &root.name)
+                .and_then(|entry| resolve_dest(config, cwd, &entry.dest).ok());
+            if let Some(Destination::Local(path)) = dest {
+                dirs.push(path.clone());
+            }
+        }
+    } else {
+        for (name, entry) in effective_mapping(config, cwd) {
+            if name == "*" {
+                continue;
+            }
+            if let Ok(Destination::Local(path)) = resolve_dest(config, cwd, &entry.dest) {
+                if let Some(parent) = path.parent() {
+                    dirs.push(parent.to_owned());
+                }
+            }
+        }
+    }
+    let mut seen = BTreeSet::new();
+    dirs.retain(|dir| seen.insert(dir.clone()));
+    let cdpath = env::join_paths(&dirs)
+        .map_err(|e| format!("can't make a CDPATH of these directories: {}", e))?;
+    println!("{}", cdpath.to_string_lossy());
+    Ok(())
+}
+
+/// Print the most specific context matching `cwd`, and where it's defined.
+fn print_context(config: &Configuration, cwd: &Path) {
+    let Some((context_path, map)) = matching_contexts(config, cwd).into_iter().next() else {
+        return;
+    };
+    let mut sources = map.values()
+        .map(|entry| entry.source_file.display().to_string())
+        .collect::<Vec<_>>();
+    sources.sort();
+    sources.dedup();
+    if sources.is_empty() {
+        println!("{}", context_path.display());
+    } else {
+        println!("{}\t{}", context_path.display(), sources.join(", "));
+    }
+}
+
+/// Look for directories called `name` one or two levels below the destinations of the given root
+/// shortcuts, other than those `.gotoignore` files rule out, and rank them, best first. Each one
+/// scores its root's weight, halved if it's two levels down, and increased by up to as much again
+/// for having been visited recently: by half for a visit a day ago, a third for two days ago, and
+/// so on. Ties go to earlier roots.
+fn search_roots(config: &Configuration, cwd: &Path, roots: &[SearchRoot], name: &str)
+    -> Vec<PathBuf>
+{
+    const DAY: f64 = 24. * 60. * 60.;
+    let last_visits = history::last_visits();
+    let now = history::now();
+    let recency = |path: &Path| match last_visits.get(path) {
+        Some(&time) => 1. / (1. + now.saturating_sub(time) as f64 / DAY),
+        None => 0.,
+    };
+
+    let mut found = Vec::<(PathBuf, f64)>::new();
+    for root in roots {
+        let dest = lookup(config, cwd, &root.name)
+            .and_then(|entry| resolve_dest(config, cwd, &entry.dest).ok());
+        let Some(Destination::Local(root_path)) = dest else {
+            continue;
+        };
+        let mut ignore = ignore::Ignore::for_dir(&root_path);
+        let mut candidates = vec![(root_path.join(name), 1.)];
+        if let Ok(entries) = std::fs::read_dir(root_path) {
+            let mut subdirs = entries.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir() && !ignore.is_ignored(path))
+                .collect::<Vec<_>>();
+            subdirs.sort();
+            for sub in &subdirs {
+                ignore.add_dir(sub);
+            }
+            candidates.extend(subdirs.into_iter().map(|sub| (sub.join(name), 0.5)));
+        }
+        for (path, depth_factor) in candidates {
+            if !path.is_dir() || ignore.is_ignored(&path) {
+                continue;
+            }
+            let score = root.weight * depth_factor * (1. + recency(&path));
+            match found.iter_mut().find(|(other, _)| *other == path) {
+                Some((_, other_score)) => *other_score = other_score.max(score),
+                None => found.push((path, score)),
+            }
+        }
+    }
+
+    // The sort is stable, so ties stay in the order of the roots.
+    found.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    found.into_iter().map(|(path, _)| path).collect()
+}
+
+/// Check that appending `extra` to a local destination leads to an existing file or directory. If
+/// not, the error message points out the nearest directory which does exist, and any names in it
+/// that are similar to the missing one.
+fn verify_extra(dest: &Destination, extra: &str) -> Result<(), Diagnostic> {
+    let Destination::Local(path) = dest else {
+        return Ok(());
+    };
+    let target = path.join(extra);
+    if extra.is_empty() || target.exists() {
+        return Ok(());
+    }
+
+    let Some(existing) = target.ancestors().find(|p| p.is_dir()) else {
+        return Err(Diagnostic::new("missing-path", format!("{:?} does not exist", target)));
+    };
+    let mut msg = format!("{:?} does not exist; the nearest existing directory is {:?}",
+        target, existing);
+
+    let missing = target.strip_prefix(existing).ok()
+        .and_then(|rest| rest.components().next())
+        .and_then(|c| c.as_os_str().to_str());
+    if let (Some(missing), Ok(entries)) = (missing, std::fs::read_dir(existing)) {
+        let mut similar = entries.flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_owned))
+            .filter(|name| fuzzy::is_similar(missing, name))
+            .collect::<Vec<_>>();
+        similar.sort();
+        if !similar.is_empty() {
+            msg += &format!("\ndid you mean: {}", similar.join(", "));
+        }
+    }
+    Err(Diagnostic::new("missing-path", msg))
+}
+
+/// For a local destination which doesn't exist, find where it was probably meant to be, as after
+/// a directory on the way to it was renamed: the first directory in its path which doesn't exist
+/// is replaced with the similarly named one next to it, preferring one which differs only in case.
+/// Returns the path of the missing directory, and of the one to use instead, if that makes a path
+/// which exists, and there's only one to choose.
+fn correct_missing_path(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let existing = path.ancestors().find(|p| p.is_dir())?;
+    let missing = path.strip_prefix(existing).ok()?.components().next()?.as_os_str().to_str()?;
+    let similar = std::fs::read_dir(existing).ok()?
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(str::to_owned))
+        .filter(|name| fuzzy::is_similar(missing, name))
+        .collect::<Vec<_>>();
+    let same_but_case = similar.iter()
+        .filter(|name| name.to_lowercase() == missing.to_lowercase())
+        .collect::<Vec<_>>();
+    let name = match (&same_but_case[..], &similar[..]) {
+        ([name], _) => name.as_str(),
+        ([], [name]) => name.as_str(),
+        _ => return None,
+    };
+    let wrong = existing.join(missing);
+    let right = existing.join(name);
+    let rest = path.strip_prefix(&wrong).ok()?;
+    right.join(rest).exists().then_some((wrong, right))
+}
+
+/// If `path`, from a shortcut defined in `source_file`, doesn't exist but a slightly different one
+/// does, say so, and if the file is `editable`, offer to correct it. Returns the corrected path if
+/// it was.
+fn offer_path_correction(path: &Path, source_file: &Path, editable: bool, home: &Path)
+    -> Option<PathBuf>
+{
+    let (wrong, right) = correct_missing_path(path)?;
+    output::warn("missing-path", format!("{:?} doesn't exist, but {:?} does", wrong, right));
+    let interactive = io::stdin().is_terminal() && !output::is_quiet();
+    let question = format!("Update {:?} to match?", source_file);
+    if !(editable && interactive && confirm(&question)) {
+        return None;
+    }
+    // The user asked for this one, so it applies even if the shortcut is pinned.
+    let files = [source_file.to_owned()];
+    if let Err(msg) = rename_paths(&files, (&wrong, &right, home), true, false) {
+        exit(&msg, true);
+    }
+    match path.strip_prefix(&wrong) {
+        Ok(rest) if !rest.as_os_str().is_empty() => Some(right.join(rest)),
+        _ => Some(right),
+    }
+}
+
+/// Remove "." components from a path, and ".." ones along with the component before them, without
+/// looking at the filesystem. This is how the shell's `cd` treats them, which is different from
+/// how the OS does if the component before a ".." is a symbolic link.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                // ".." at the root is the root.
+                Some(Component::RootDir | Component::Prefix(_)) => (),
+                _ => normalized.push(component),
+            },
+            _ => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(Component::CurDir);
+    }
+    normalized
+}
+
+/// Express `path` relative to `base`, using ".." components as needed. Both must be absolute.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(path_components);
+    if relative.as_os_str().is_empty() {
+        relative.push(Component::CurDir);
+    }
+    relative
+}
+
+/// Single-quote a string so the shell evaluates it literally, without any expansion.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Resolve a name, or a name followed by "/" and a path below it, to the destination it leads to,
+/// with that path appended, running it if it's a command.
+fn resolve_name(config: &Configuration, cwd: &Path, name: &str)
+    -> Result<Destination, Diagnostic>
+{
+    resolve_name_entry(config, cwd, name).map(|(dest, _)| dest)
+}
+
+/// Like `resolve_name`, but also returns the shortcut used.
+fn resolve_name_entry<'a>(config: &'a Configuration, cwd: &Path, name: &str)
+    -> Result<(Destination, &'a PathMappingEntry), Diagnostic>
+{
+    let (entry, rest) = match lookup(config, cwd, name) {
+        Some(entry) => (entry, ""),
+        None => name.split_once('/')
+            .and_then(|(first, rest)| Some((lookup(config, cwd, first)?, rest)))
+            .ok_or_else(|| {
+                Diagnostic::new("not-found", format!("{:?} isn't a shortcut here", name))
+                    .with_key(name)
+            })?,
+    };
+    let dest = run_dest_command(&resolve_dest(config, cwd, &entry.dest)?)?;
+    let dest = match dest {
+        Destination::Local(path) => Destination::Local(normalize_lexically(&path.join(rest))),
+        Destination::Ssh { host, path } => {
+            Destination::Ssh { host, path: join_foreign_path(&path, rest) }
+        }
+        _ => dest,
+    };
+    Ok((dest, entry))
+}
+
+/// Open a directory in the system's file manager.
+fn reveal(path: &Path) -> Result<(), Diagnostic> {
+    let program = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let status = std::process::Command::new(program)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    // Explorer exits with 1 even when it succeeds.
+    if !status.success() && !cfg!(windows) {
+        return Err(format!("{} exited with {}", program, status).into());
+    }
+    Ok(())
+}
+
+/// Open each of a workspace's shortcuts in a tmux window of its own, named after the shortcut.
+/// Inside tmux, they're added to the current session. Otherwise they make a new session, named
+/// after the workspace, and this prints the command to attach to it, for the shell to run; if
+/// there's already a session by that name, it's just attached to.
+fn open_workspace(config: &Configuration, cwd: &Path, name: &str) -> Result<(), Diagnostic> {
+    let shortcuts = config.workspaces.get(name).ok_or_else(|| {
+        Diagnostic::new("not-found", format!("there's no workspace named {:?} here", name))
+    })?;
+    let mut windows = vec![];
+    for shortcut in shortcuts {
+        match resolve_name(config, cwd, shortcut)? {
+            Destination::Local(path) if path.is_dir() => windows.push((shortcut, path)),
+            Destination::Local(path) => {
+                return Err(format!("{:?} leads to {:?}, which isn't a directory", shortcut, path)
+                    .into());
+            }
+            dest => return Err(format!("{:?} leads to {}, which isn't on this machine",
+                shortcut, dest).into()),
+        }
+    }
+
+    let tmux = |args: &[&OsStr]| -> Result<bool, Diagnostic> {
+        std::process::Command::new("tmux")
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .map_err(|e| format!("failed to run tmux, which workspaces need: {}", e).into())
+    };
+    let session = format!("={}", name);
+    let inside = env::var_os("TMUX").is_some();
+    if !inside && tmux(&["has-session".as_ref(), "-t".as_ref(), session.as_ref()])? {
+        println!("tmux attach-session -t {}", shell_quote(&session));
+        return Ok(());
+    }
+    for (i, (shortcut, path)) in windows.iter().enumerate() {
+        let mut args: Vec<&OsStr> = match (inside, i) {
+            (true, _) => vec!["new-window".as_ref()],
+            (false, 0) => vec!["new-session".as_ref(), "-d".as_ref(), "-s".as_ref(), name.as_ref()],
+            (false, _) => vec!["new-window".as_ref(), "-t".as_ref(), session.as_ref()],
+        };
+        args.extend(["-n".as_ref(), shortcut.as_ref(), "-c".as_ref(), path.as_os_str()]);
+        if !tmux(&args)? {
+            return Err(format!("tmux failed to open a window for {:?}", shortcut).into());
+        }
+    }
+    if !inside {
+        println!("tmux attach-session -t {}", shell_quote(&session));
+    }
+    Ok(())
+}
+
+/// Get `dest`, with `extra` after it, ready to go to, as the shortcut `entry` (if any) asks:
+/// resolve it and run its command, mount it, offer to correct its path if it's gone missing,
+/// create it, and check that `extra` leads somewhere. `home` is the home directory and its
+/// configuration file.
+fn prepare_dest(
+    config: &Configuration,
+    cwd: &Path,
+    (home, config_path): (&Path, &Path),
+    dest: &Destination,
+    extra: &str,
+    entry: Option<&PathMappingEntry>,
+    args: &Args,
+) -> Result<Destination, Diagnostic> {
+    let create = entry.is_some_and(|entry| entry.create);
+    let mount = entry.and_then(|entry| entry.mount.as_deref());
+    let dest = timing::time(|| format!("resolve {}", dest), || {
+        resolve_dest(config, cwd, dest).and_then(|dest| run_dest_command(&dest))
+    })?;
+    if let (Some(mount), Destination::Local(path)) = (mount, &dest) {
+        mount_dest(path, mount)?;
+    }
+    // If the shortcut leads somewhere that doesn't exist, but did under a slightly different
+    // name, say so, and offer to fix it.
+    let dest = match (entry, dest) {
+        (Some(entry), Destination::Local(path)) if !create && !path.exists() => {
+            let editable = config_file_paths(config_path, cwd).contains(&entry.source_file);
+            let corrected = offer_path_correction(&path, &entry.source_file, editable, home);
+            Destination::Local(corrected.unwrap_or(path))
+        }
+        (_, dest) => dest,
+    };
+    if let (true, Destination::Local(path)) = (args.create || create, &dest) {
+        let full = path.join(extra);
+        std::fs::create_dir_all(&full)
+            .map_err(|e| format!("failed to create {:?}: {}", full, e))?;
+    }
+    if !args.no_verify && config.settings.verify_extra.unwrap_or(true) {
+        verify_extra(&dest, extra)?;
+    }
+    Ok(dest)
+}
+
+/// Go to each of several names at once, for `--each`: in a tmux pane or window of its own, or by
+/// printing the command to go to each one on a line of its own, for scripts. Every name is
+/// resolved before any of them are gone to, and if any can't be, each failure is reported, and
+/// none of them are.
+fn go_each(
+    config: &Configuration,
+    cwd: &Path,
+    home: (&Path, &Path),
+    names: &[&str],
+    args: &Args,
+) -> Result<(), Diagnostic> {
+    let record_history = config.settings.record_history.unwrap_or(true);
+    let inside = env::var_os("TMUX").is_some();
+    let each_in = args.each_in.unwrap_or(if inside { EachIn::Panes } else { EachIn::Lines });
+    if each_in != EachIn::Lines && !inside {
+        return Err("--each-in=panes and --each-in=windows only work inside tmux".to_owned().into());
+    }
+
+    let mut resolved = vec![];
+    let mut failures = 0;
+    for name in names {
+        match resolve_name_entry(config, cwd, name) {
+            Ok((dest, entry)) => resolved.push((*name, dest, entry)),
+            Err(diag) => {
+                report(&diag);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        return Err(format!("{} of the {} names given couldn't be resolved, so none were gone to",
+            failures, names.len()).into());
+    }
+
+    let terminator = if args.print0 { '\0' } else { '\n' };
+    let mut split = false;
+    for (name, dest, entry) in resolved {
+        if entry.confirm && !args.yes {
+            let question = format!("go to {}?", name);
+            match confirm_on_terminal(&question) {
+                Ok(true) => (),
+                Ok(false) => {
+                    output::note(format!("not going to {}", dest));
+                    continue;
+                }
+                Err(e) => return Err(format!("{} needs confirming, but there's no terminal to \
+                    ask on ({}); pass --yes to go anyway", dest, e).into()),
+            }
+        }
+        let dest = prepare_dest(config, cwd, home, &dest, "", Some(entry), args)?;
+        let cd_cmd = entry.cd_cmd.as_deref().unwrap_or(&args.command);
+        let (command, went_to) = match &dest {
+            Destination::Local(path) => {
+                (path_command(path, cd_cmd, "", args.shell), path.display().to_string())
+            }
+            _ => (dest_command(&dest, args, ""), dest.to_string()),
+        };
+        match each_in {
+            EachIn::Lines if args.print_path => print!("{}{}", went_to, terminator),
+            EachIn::Lines => println!("{}", command),
+            EachIn::Panes | EachIn::Windows => {
+                let mut tmux = std::process::Command::new("tmux");
+                if each_in == EachIn::Panes {
+                    tmux.arg("split-window");
+                    split = true;
+                } else {
+                    tmux.args(["new-window", "-n", name]);
+                }
+                // Local directories are opened in a new shell there, and anywhere else by running
+                // the command that goes there.
+                match &dest {
+                    Destination::Local(path) => tmux.arg("-c").arg(path),
+                    _ => tmux.arg(&command),
+                };
+                let status = tmux.status()
+                    .map_err(|e| format!("failed to run tmux: {}", e))?;
+                if !status.success() {
+                    return Err(format!("tmux failed to open a {} for {:?}",
+                        if split { "pane" } else { "window" }, name).into());
+                }
+            }
+        }
+        if record_history {
+            let _ = history::record_jump(Some(name), &went_to, cwd);
+        }
+    }
+    // Panes get smaller with each split, so spread them out evenly.
+    if split {
+        let _ = std::process::Command::new("tmux").args(["select-layout", "tiled"]).status();
+    }
+    Ok(())
+}
+
+/// Resolve the names read from stdin, for `--batch`. The configuration in effect in each directory
+/// is read only once. Each path printed ends with `terminator`. A directory whose configuration
+/// can't be read is reported once, and the names in it print nothing. Returns whether all of them
+/// were resolved.
+fn resolve_batch(config_path: &Path, cwd: &Path, git: bool, terminator: char)
+    -> Result<bool, Diagnostic>
+{
+    let mut configs = BTreeMap::<PathBuf, Option<Configuration>>::new();
+    let mut all_resolved = true;
+    let mut out = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|e| format!("failed to read stdin: {}", e))?;
+        let (dir, name) = match line.split_once('\t') {
+            Some((dir, name)) => (cwd.join(dir), name),
+            None => (cwd.to_owned(), line.as_str()),
+        };
+        let config = match configs.entry(dir.clone()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let repo_root = if git { find_repo_root(&dir) } else { None };
+                let config = read_combine_configs(config_path, &dir, repo_root)
+                    .map_err(|diag| report(&diag))
+                    .ok();
+                entry.insert(config)
+            }
+        };
+        let resolved = match config.as_ref().map(|config| resolve_name(config, &dir, name)) {
+            Some(Ok(Destination::Local(path))) => path.display().to_string(),
+            Some(Ok(dest)) => dest.to_string(),
+            Some(Err(diag)) => {
+                report(&diag);
+                all_resolved = false;
+                String::new()
+            }
+            None => {
+                all_resolved = false;
+                String::new()
+            }
+        };
+        write!(out, "{}{}", resolved, terminator)
+            .map_err(|e| format!("failed to write output: {}", e))?;
+    }
+    Ok(all_resolved)
+}
+
+/// A hint for when goto's output goes straight to the terminal: that it needs the shell function
+/// to change the shell's directory, and how to set it up for `shell`, or if that's the default,
+/// for the user's shell, if it's one goto knows.
+fn wrapper_hint(shell: shell::Shell) -> String {
+    let shell = match shell {
+        shell::Shell::Sh => {
+            let user_shell = env::var_os("SHELL").map(PathBuf::from).unwrap_or_default();
+            [shell::Shell::Bash, shell::Shell::Zsh, shell::Shell::Fish].into_iter()
+                .find(|shell| user_shell.file_name() == Some(shell.name().as_ref()))
+                .unwrap_or(shell::Shell::Bash)
+        }
+        shell => shell,
+    };
+    let binary = env::current_exe()
+        .map(|path| shell.quote(&path.to_string_lossy()))
+        .unwrap_or_else(|_| "goto".to_owned());
+    let line = match shell {
+        shell::Shell::Fish => format!("{} init fish | source", binary),
+        shell::Shell::Powershell => {
+            format!("Invoke-Expression (& {} init powershell | Out-String)", binary)
+        }
+        shell::Shell::Cmd => format!("for /f \"delims=\" %i in ('{} init cmd') do @%i", binary),
+        _ => format!("eval \"$({} init {})\"", binary, shell.name()),
+    };
+    format!("goto can't change the shell's directory by itself: it prints a command for the \
+        shell to run, which is what the shell function from 'goto init' does. Add this to your {} \
+        startup file to set it up:\n    {}\nOr set 'unwrapped = \"subshell\"' in the \
+        [settings] table to start a new shell there instead.", shell.name(), line)
+}
+
+/// Start a new shell in `dir`, for when goto is run without the shell function and the
+/// "unwrapped" setting says to. Returns its exit status.
+fn run_subshell(dir: &Path) -> Result<i32, Diagnostic> {
+    let program = if cfg!(windows) {
+        env::var_os("COMSPEC").unwrap_or_else(|| "cmd".into())
+    } else {
+        env::var_os("SHELL").unwrap_or_else(|| "sh".into())
+    };
+    output::note(format!("starting a new shell in {:?}; exit it to go back", dir));
+    let status = std::process::Command::new(&program)
+        .current_dir(dir)
+        .status()
+        .map_err(|e| format!("failed to run {:?}: {}", program, e))?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Make the command which changes to the given local directory.
+fn path_command(path: &Path, shellcmd: &str, extra: &str, shell: shell::Shell) -> String {
+    // Because the path is potentially combined with the current working directory, which is
+    // untrusted data, and the path is going to be evaluated by the shell, the path needs to be
+    // quoted to prevent any expansion, for security.
+    // (Otherwise a folder named '$(:(){:|:&};:)' would make for a bad day.)
+    shell.change_dir_command(shellcmd, path.join(extra).to_str().unwrap())
+}
+
+/// Check the <extra> arguments, joined together, before they're appended to a destination. An
+/// absolute path would replace the destination entirely, so unless `absolute` says otherwise, it's
+/// an error.
+fn join_extra(extra: &str, absolute: AbsoluteExtra) -> Result<String, String> {
+    let path = Path::new(extra);
+    if !path.has_root() && !path.is_absolute() {
+        return Ok(extra.to_owned());
+    }
+    match absolute {
+        AbsoluteExtra::Reject => Err(format!("{:?} is an absolute path, so it would replace the \
+            destination instead of being added to it; use --absolute-extra=replace to go there \
+            instead, or --absolute-extra=relative to add it anyway", extra)),
+        AbsoluteExtra::Replace => Ok(extra.to_owned()),
+        AbsoluteExtra::Relative => Ok(path.components()
+            .filter(|c| !matches!(c, Component::Prefix(_) | Component::RootDir))
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/")),
+    }
+}
+
+/// Append `extra` to a path which is not on this machine, and so can't be handled with `Path`.
+fn join_foreign_path(path: &str, extra: &str) -> String {
+    let mut joined = path.to_owned();
+    if !extra.is_empty() {
+        if !joined.is_empty() && !joined.ends_with('/') {
+            joined.push('/');
+        }
+        joined.push_str(extra);
+    }
+    joined
+}
+
+/// Make a command which logs in to `host` and starts a shell in `path`.
+fn remote_command(host: &str, path: &str, ssh_cmd: &str, extra: &str) -> String {
+    let remote_path = join_foreign_path(path, extra);
+
+    // The remote command is evaluated twice: once by the local shell, and again by the remote
+    // one, so the path gets quoted for the remote side and then the whole command is quoted again.
+    // $SHELL is left for the remote shell to expand.
+    let remote_cmd = if remote_path.is_empty() {
+        "exec $SHELL".to_owned()
+    } else {
+        format!("cd {} && exec $SHELL", shell_quote(&remote_path))
+    };
+    format!("{} {} {}", ssh_cmd, shell_quote(host), shell_quote(&remote_cmd))
+}
+
+/// Make a command which runs `shell` inside the container `name`, in directory `path`.
+fn container_command(name: &str, path: &str, shell: &str, container_cmd: &str, extra: &str)
+    -> String
+{
+    let mut command = format!("{} ", container_cmd);
+    let path = join_foreign_path(path, extra);
+    if !path.is_empty() {
+        command += &format!("-w {} ", shell_quote(&path));
+    }
+    command + &format!("{} {}", shell_quote(name), shell_quote(shell))
+}
+
+/// Make a command which sets the terminal's title, if the terminal is known to support it.
+fn title_command(title: &str) -> Option<String> {
+    let term = env::var("TERM").unwrap_or_default();
+    let format = if term.is_empty() || term == "dumb" || term == "linux" {
+        return None;
+    } else if term.starts_with("screen") || term.starts_with("tmux") {
+        // screen and tmux name the window with their own escape sequence.
+        r"\033k%s\033\\"
+    } else {
+        r"\033]0;%s\007"
+    };
+    // Control characters in the title could end the escape sequence early and start another.
+    let title = title.chars().filter(|c| !c.is_control()).collect::<String>();
+    Some(format!("printf {} {}", shell_quote(format), shell_quote(&title)))
+}
+
+/// Whether the terminal is one known to understand the OSC 7 escape sequence, which tells it the
+/// working directory.
+fn terminal_reports_cwd() -> bool {
+    let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+    ["WezTerm", "Apple_Terminal", "iTerm.app", "ghostty"].contains(&term_program.as_str())
+        || env::var_os("KITTY_WINDOW_ID").is_some()
+        || env::var_os("VTE_VERSION").is_some()
+}
+
+/// This machine's host name, for OSC 7, which includes it so that terminals can tell a local
+/// directory from one on a machine logged in to, and for picking the host table from
+/// configuration files. Empty if it can't be found.
+fn host_name() -> &'static str {
+    static HOST_NAME: OnceLock<String> = OnceLock::new();
+    HOST_NAME.get_or_init(|| {
+        env::var("HOSTNAME").ok()
+            .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .or_else(|| {
+                let output = std::process::Command::new("hostname").output().ok()?;
+                String::from_utf8(output.stdout).ok()
+            })
+            .map(|name| name.trim().to_owned())
+            .unwrap_or_default()
+    })
+}
+
+/// Make a command which tells the terminal the new working directory, as a file:// URL in the
+/// OSC 7 escape sequence, if the terminal is known to understand it.
+fn report_cwd_command(path: &Path) -> Option<String> {
+    if !terminal_reports_cwd() {
+        return None;
+    }
+    let mut url = format!("file://{}", host_name());
+    for &b in path.to_string_lossy().as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            url.push(char::from(b));
+        } else {
+            url += &format!("%{:02X}", b);
+        }
+    }
+    Some(format!("printf {} {}", shell_quote(r"\033]7;%s\033\\"), shell_quote(&url)))
+}
+
+/// Run a command destination's command, and take the first line it prints as the path of a local
+/// destination. Other destinations are returned as they are.
+fn run_dest_command(dest: &Destination) -> Result<Destination, Diagnostic> {
+    let Destination::Command { cmd, dir } = dest else {
+        return Ok(dest.clone());
+    };
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let output = std::process::Command::new(shell)
+        .arg(flag)
+        .arg(cmd)
+        .current_dir(dir)
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .map_err(|e| Diagnostic::new("command-failed", format!("failed to run {:?}: {}", cmd, e)))?;
+    if !output.status.success() {
+        return Err(Diagnostic::new("command-failed",
+            format!("{:?} failed with {}", cmd, output.status)));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().map(str::trim_end).find(|line| !line.is_empty()) {
+        Some(line) => Ok(Destination::Local(dir.join(line))),
+        None => Err(Diagnostic::new("command-failed", format!("{:?} printed no path", cmd))),
+    }
+}
+
+/// Make a directory which needs mounting available, by running its mount command, if it doesn't
+/// exist or is empty, as mount points are until something is mounted on them. The command can ask
+/// for a password, but what it prints goes to stderr, so the shell doesn't take it as a command.
+fn mount_dest(path: &Path, mount: &str) -> Result<(), Diagnostic> {
+    let available = std::fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_some());
+    if available {
+        return Ok(());
+    }
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let status = std::process::Command::new(shell)
+        .arg(flag)
+        .arg(mount)
+        .stdout(io::stderr())
+        .status()
+        .map_err(|e| Diagnostic::new("command-failed",
+            format!("failed to run {:?}: {}", mount, e)))?;
+    if !status.success() {
+        return Err(Diagnostic::new("command-failed",
+            format!("{:?} failed with {}", mount, status)));
+    }
+    Ok(())
+}
+
+impl Destination {
+    /// Whether going to this destination can mean running a command, directly or through one of
+    /// its alternatives.
+    /// Whether where this leads depends on where other shortcuts lead.
+    fn refers_to_shortcuts(&self) -> bool {
+        match self {
+            Destination::Alias(_) => true,
+            Destination::Alternatives(alternatives) => {
+                alternatives.iter().any(Self::refers_to_shortcuts)
+            }
+            Destination::NewestChild(dest) => dest.refers_to_shortcuts(),
+            Destination::LastVisited { fallback, .. } => fallback.refers_to_shortcuts(),
+            _ => false,
+        }
+    }
+
+    fn runs_command(&self) -> bool {
+        match self {
+            Destination::Command { .. } => true,
+            Destination::Alternatives(alternatives) => alternatives.iter().any(Self::runs_command),
+            Destination::NewestChild(dest) => dest.runs_command(),
+            Destination::LastVisited { fallback, .. } => fallback.runs_command(),
+            _ => false,
+        }
+    }
+}
+
+/// Make the command which takes you to the given destination.
+fn dest_command(dest: &Destination, args: &Args, extra: &str) -> String {
+    match dest {
+        Destination::Local(path) => path_command(path, &args.command, extra, args.shell),
+        Destination::Ssh { host, path } => remote_command(host, path, &args.ssh_command, extra),
+        Destination::Container { name, path, shell } => {
+            container_command(name, path, shell, &args.container_command, extra)
+        }
+        Destination::Command { .. } => unreachable!("command destinations are run before use"),
+        Destination::Alias(_)
+        | Destination::Alternatives(_)
+        | Destination::NewestChild(_)
+        | Destination::LastVisited { .. }
+        | Destination::NearestRoot { .. } => {
+            unreachable!("aliases and the like are resolved before use")
+        }
+        Destination::Disabled => unreachable!("lookups never find disabled shortcuts"),
     }
-
-    // Because the path is potentially combined with the current working directory, which is
-    // untrusted data, and the path is going to be evaluated by the shell, the path needs to be
-    // single-quote escaped to prevent any expansion, for security.
-    // (Otherwise a folder named '$(:(){:|:&};:)' would make for a bad day.)
-    println!("'{}'", path.join(extra).to_str().unwrap().replace('\'', "'\\''"));
 }
 
 fn main() {
@@ -257,64 +4316,619 @@ fn main() {
             std::process::exit(2);
         });
 
-    let extra = args.extra.as_deref().unwrap_or("");
+    if args.profile_startup {
+        timing::enable();
+    }
+    output::configure(args.quiet, args.format == OutputFormat::Json, args.message_style);
+    if let Some(profile) = args.profile.clone().or_else(|| env::var("GOTO_PROFILE").ok()) {
+        if !profile.is_empty() {
+            PROFILE.set(profile).unwrap();
+        }
+    }
+
+    let name = args.name.as_deref().unwrap_or("");
+    // With --each, <extra> is more names.
+    let extra = if args.each {
+        String::new()
+    } else {
+        join_extra(&args.extra.join("/"), args.absolute_extra)
+            .unwrap_or_else(|msg| exit(&msg, true))
+    };
+    let extra = extra.as_str();
 
     let home = dirs::home_dir().unwrap_or_else(|| {
         exit("unable to determine home directory", true);
     });
-    let config_path = home.join(Path::new(CONFIG_FILENAME));
+    let config_path = find_config_file(&home);
 
     let cwd = env::current_dir().unwrap_or_else(|e| {
         exit(&format!("unable to get current working directory: {}", e), true);
     });
+    let cwd = if args.physical_cwd { cwd } else { logical_cwd(cwd) };
+    let cwd = match &args.cwd {
+        Some(path) if args.physical_cwd => {
+            cwd.join(path).canonicalize().unwrap_or_else(|e| {
+                exit(&format!("unable to resolve {:?}: {}", path, e), true);
+            })
+        }
+        Some(path) => normalize_lexically(&cwd.join(path)),
+        None => cwd,
+    };
 
-    let config = read_combine_configs(&config_path, &cwd).unwrap_or_else(|msg| {
-        exit(&msg, true);
-    });
+    CWD.set(cwd.clone()).unwrap();
+    let repo_root = if args.git { find_repo_root(&cwd) } else { None };
 
-    // only used for the --list mode
-    let mut effective_map = PathMapping::new();
+    // The configuration files named on the command line, or all of the ones in effect.
+    let config_files_or_default = |files: &[PathBuf]| -> Vec<PathBuf> {
+        if files.is_empty() {
+            config_file_paths(&config_path, &cwd)
+                .into_iter()
+                .filter(|path| path.is_file())
+                .collect()
+        } else {
+            files.iter().map(|path| cwd.join(path)).collect()
+        }
+    };
+
+    // The definitions of a name in effect here, other than those in the given file.
+    let definitions_elsewhere = |name: &str, config_file: &Path| {
+        read_config_layers(&config_path, &cwd, repo_root).map(|layers| {
+            all_definitions(&layers, &cwd, name, &combine_layers(&layers).settings)
+                .into_iter()
+                .filter(|entry| entry.source_file != config_file)
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+    };
 
-    // Contexts can have keys that overlap with other contexts. The rule is that the longest
-    // context path that matches the CWD takes precedence.
-    let mut done = false;
-    let mut context_paths_by_len: Vec<&PathBuf> = config.contexts.keys().collect();
-    context_paths_by_len.sort_by_key(|p| p.as_os_str().len());
-    for context_path in context_paths_by_len.iter().rev() {
-        if cwd.starts_with(context_path) {
-            let map = &config.contexts[*context_path];
-            if args.list {
-                for (k, v) in map {
-                    if let Entry::Vacant(entry) = effective_map.entry(k.clone()) {
-                        entry.insert(v.clone());
+    // 'search --choose' goes on to go to the chosen match, below.
+    let search_to_go = match &args.action {
+        Some(Action::Search { query, choose: true }) => Some(query),
+        _ => None,
+    };
+
+    if let Some(action) = args.action.as_ref().filter(|_| search_to_go.is_none()) {
+        let load_config = || read_combine_configs(&config_path, &cwd, repo_root);
+        let result = match action {
+            Action::Scan { dir, depth, ignore, config } => {
+                let dir = cwd.join(dir);
+                let config = config.as_ref().map(|path| cwd.join(path));
+                scan(&dir, *depth, ignore, config.as_ref().unwrap_or(&config_path), &home)
+                    .map_err(Diagnostic::from)
+            }
+            Action::AddChildren { dir, prefix, force, include_pinned, config } => {
+                let dir = cwd.join(dir);
+                let config = config.as_ref().map(|path| cwd.join(path));
+                add_children(&dir, prefix, (*force, *include_pinned),
+                    config.as_ref().unwrap_or(&config_path), &home)
+                    .map_err(Diagnostic::from)
+            }
+            Action::Save { name, force, config } => {
+                let config = config.as_ref().map(|path| cwd.join(path));
+                let config = config.as_ref().unwrap_or(&config_path);
+                let name = match name {
+                    Some(name) => Ok(name.clone()),
+                    None => default_shortcut_name(&cwd),
+                };
+                name.map_err(Diagnostic::from)
+                    .and_then(|name| {
+                        let elsewhere = definitions_elsewhere(&name, config)?;
+                        add_shortcut(&name, &cwd, *force, config, &home, &elsewhere)
+                            .map_err(Diagnostic::from)
+                    })
+            }
+            Action::Add { name, path, temp: true, ttl, .. } => {
+                let dest = path.as_ref().map(|p| cwd.join(p)).unwrap_or_else(|| cwd.clone());
+                let ttl = ttl.as_ref().map(|ttl| {
+                    history::parse_duration(ttl).ok_or_else(|| format!("invalid --ttl {:?}: \
+                        expected a number followed by m, h, d, or w", ttl))
+                });
+                ttl.transpose()
+                    .and_then(|ttl| temp::add(name, &normalize_lexically(&dest), ttl))
+                    .map_err(Diagnostic::from)
+            }
+            Action::Add { name, path, force, config, temp: false, .. } => {
+                let config = config.as_ref().map(|path| cwd.join(path));
+                let config = config.as_ref().unwrap_or(&config_path);
+                let dest = path.as_ref().map(|p| cwd.join(p)).unwrap_or_else(|| cwd.clone());
+                definitions_elsewhere(name, config).and_then(|elsewhere| {
+                    add_shortcut(name, &dest, *force, config, &home, &elsewhere)
+                        .map_err(Diagnostic::from)
+                })
+            }
+            Action::Tidy { file, dry_run } => {
+                let path = file.as_ref().map(|path| cwd.join(path)).unwrap_or(config_path.clone());
+                tidy::tidy(&path, &home, *dry_run).map(|(changed, same_destinations)| {
+                    for same in same_destinations {
+                        output::warn("same-destination", format!("{:?}: {}", path, same));
+                    }
+                    match (changed, *dry_run) {
+                        (false, _) => output::note(format!("{:?} is already tidy", path)),
+                        (true, true) => output::note(format!("{:?} needs tidying", path)),
+                        (true, false) => output::note(format!("tidied {:?}", path)),
                     }
+                }).map_err(Diagnostic::from)
+            }
+            Action::Conflicts { list } => {
+                read_config_layers(&config_path, &cwd, repo_root).and_then(|layers| {
+                    let settings = combine_layers(&layers).settings;
+                    output::set_templates(settings.messages.clone().unwrap_or_default());
+                    let editable = config_files_or_default(&[]);
+                    let edit = !list && io::stdin().is_terminal();
+                    conflicts(&layers, &cwd, &settings, &editable, edit).map_err(Diagnostic::from)
+                })
+            }
+            Action::Migrate { files, dry_run } => {
+                migrate_files(&config_files_or_default(files), *dry_run).map_err(Diagnostic::from)
+            }
+            Action::RenamePath { old, new, files, include_pinned, dry_run } => {
+                let old = normalize_lexically(&cwd.join(old));
+                let new = normalize_lexically(&cwd.join(new));
+                let files = config_files_or_default(files);
+                rename_paths(&files, (&old, &new, &home), *include_pinned, *dry_run)
+                    .map_err(Diagnostic::from)
+            }
+            Action::Doctor => load_config().map(|config| doctor(&config, &cwd)),
+            Action::Prune { files, yes, include_pinned } => {
+                prune_files(&config_files_or_default(files), *yes, *include_pinned)
+                    .map_err(Diagnostic::from)
+            }
+            Action::Completions { shell, name } => {
+                env::current_exe()
+                    .map(|binary| {
+                        let mut script = vec![];
+                        clap_complete::generate(*shell, &mut Args::command(), name, &mut script);
+                        print!("{}", String::from_utf8_lossy(&script));
+                        let binary = binary.to_string_lossy();
+                        if let Some(dynamic) = complete::dynamic_script(*shell, name, &binary) {
+                            print!("{}", dynamic);
+                        }
+                    })
+                    .map_err(|e| {
+                        Diagnostic::from(format!("unable to find the goto program: {}", e))
+                    })
+            }
+            Action::Complete { words } => load_config().map(|config| {
+                let subcommands = Args::command().get_subcommands()
+                    .map(|cmd| cmd.get_name().to_owned())
+                    .collect::<Vec<_>>();
+                for candidate in complete::candidates(&config, &cwd, words, &subcommands) {
+                    println!("{}", candidate);
+                }
+            }),
+            Action::NewConfig { home: in_home, seed } => {
+                new_config(if *in_home { &home } else { &cwd }, *seed).map(|path| {
+                    output::note(format!("created {:?}", path));
+                }).map_err(Diagnostic::from)
+            }
+            Action::Undo => backup::undo().map_err(Diagnostic::from),
+            Action::WhichConfig => {
+                which_config(&config_files_or_default(&[]));
+                Ok(())
+            }
+            Action::Daemon => daemon::run().map_err(Diagnostic::from),
+            Action::Init { shell, name, track } => {
+                if !shell::is_valid_function_name(name) {
+                    Err(Diagnostic::from(format!("{:?} is not a valid function name", name)))
+                } else {
+                    env::current_exe()
+                        .map_err(|e| {
+                            Diagnostic::from(format!("unable to find the goto program: {}", e))
+                        })
+                        .and_then(|binary| {
+                            let binary = binary.to_string_lossy();
+                            let hook = match (track, shell::visit_hook(*shell, name, &binary)) {
+                                (false, _) => String::new(),
+                                (true, Some(hook)) => hook,
+                                (true, None) => return Err(Diagnostic::from(
+                                    "--track is only supported in bash, zsh, and fish".to_owned())),
+                            };
+                            print!("{}{}{}", shell::session_export(*shell),
+                                shell::shell_function(*shell, name, &binary), hook);
+                            Ok(())
+                        })
+                }
+            }
+            Action::History { since, format } => {
+                since.as_deref().map(history::parse_since).unwrap_or(Ok(0))
+                    .and_then(|since| {
+                        history::jumps(since)
+                            .map_err(|e| format!("failed to read the history: {}", e))
+                    })
+                    .map(|jumps| print_history(&jumps, *format))
+                    .map_err(Diagnostic::from)
+            }
+            Action::Visit { dir } => {
+                history::record_visit(&cwd.join(dir))
+                    .map_err(|e| Diagnostic::from(format!("failed to record visit: {}", e)))
+            }
+            Action::Search { query, .. } => load_config().map(|config| {
+                let formatter = list::ListFormatter::new();
+                for (name, entry) in search::search(&config, &cwd, query) {
+                    output::print("search", formatter.format_entry(&name, &entry));
+                }
+            }),
+            Action::Linkfarm { dir, clean } => load_config().and_then(|config| {
+                link_farm(&config, &cwd, &cwd.join(dir), *clean).map_err(Diagnostic::from)
+            }),
+            Action::Export { what: ExportKind::Cdpath } => load_config().and_then(|config| {
+                print_cdpath(&config, &cwd).map_err(Diagnostic::from)
+            }),
+            Action::Env { prefix } => load_config().map(|config| print_env(&config, &cwd, prefix)),
+            Action::Reveal { name, extra } => load_config().and_then(|config| {
+                let name = [std::slice::from_ref(name), extra].concat().join("/");
+                match resolve_name(&config, &cwd, &name)? {
+                    Destination::Local(path) => reveal(&path),
+                    dest => Err(format!("{} isn't on this machine", dest).into()),
                 }
-            } else if let Some(entry) = map.get(&args.name) {
-                print_path(&entry.dest, &args.command, extra);
-                done = true;
-                break;
+            }),
+            Action::Context => load_config().map(|config| print_context(&config, &cwd)),
+            Action::Workspace { name } => load_config().and_then(|config| {
+                open_workspace(&config, &cwd, name)
+            }),
+            Action::Breadcrumb => load_config().map(|config| {
+                if let Some(crumb) = breadcrumb(&config, &cwd) {
+                    println!("{}", crumb);
+                }
+            }),
+            Action::NameOf { path } => {
+                let path = path.as_ref().map(|p| cwd.join(p)).unwrap_or_else(|| cwd.clone());
+                load_config().and_then(|config| {
+                    name_of(&config, &cwd, &path).map_err(Diagnostic::from)
+                })
             }
+        };
+        match result {
+            Ok(()) => return,
+            Err(diag) => fail(&diag, true),
+        }
+    }
+
+    let terminator = if args.print0 { '\0' } else { '\n' };
+    if args.batch {
+        match resolve_batch(&config_path, &cwd, args.git, terminator) {
+            Ok(true) => return,
+            Ok(false) => ::std::process::exit(1),
+            Err(diag) => fail(&diag, true),
         }
     }
 
+    // Going to one name, without looking at its other definitions or the other names, only needs
+    // the files which bear on that name.
+    let plain = !args.list && !args.all && !args.each && !args.choose && search_to_go.is_none();
+    let layers = if plain && !name.is_empty() {
+        read_config_layers_for(&config_path, &cwd, repo_root, name)
+    } else {
+        read_config_layers(&config_path, &cwd, repo_root)
+    };
+    let layers = layers.unwrap_or_else(|diag| fail(&diag, true));
+    let config = combine_layers(&layers);
+    output::set_templates(config.settings.messages.clone().unwrap_or_default());
+
     if args.list {
-        for (k, v) in config.global {
-            if let Entry::Vacant(entry) = effective_map.entry(k) {
-                entry.insert(v);
+        let stale_after = config.settings.stale_after.unwrap_or(DEFAULT_STALE_AFTER);
+        let mut entries = effective_mapping(&config, &cwd)
+            .into_iter()
+            .filter(|(_, v)| !args.local || v.context.is_some() || v.source_file != config_path)
+            .collect::<Vec<_>>();
+        let formatter = list::ListFormatter::new()
+            .with_staleness(history::Staleness::new(stale_after))
+            .checking(entries.iter().map(|(_, v)| v));
+        // Entries come sorted by name already; the sort is stable, so that's the tie-breaker.
+        match args.sort {
+            ListSort::Name => entries.sort_by(|(a, _), (b, _)| a.split('.').cmp(b.split('.'))),
+            ListSort::Path => entries.sort_by_key(|(_, v)| v.dest.to_string()),
+            ListSort::Source => entries.sort_by(|(_, a), (_, b)| a.source_file.cmp(&b.source_file)),
+            ListSort::Recency => {
+                let last_visits = history::last_visits();
+                entries.sort_by_key(|(_, v)| std::cmp::Reverse(match &v.dest {
+                    Destination::Local(path) => history::last_visit_within(last_visits, path),
+                    _ => None,
+                }));
+            }
+        }
+        match args.format {
+            OutputFormat::Tsv | OutputFormat::Csv => {
+                for (k, v) in entries {
+                    let fields = list::record_fields(&k, &v);
+                    if args.format == OutputFormat::Tsv {
+                        println!("{}", list::tsv_record(&fields));
+                    } else {
+                        println!("{}", list::csv_record(&fields));
+                    }
+                }
+            }
+            // Sorted by name, dotted names are shown as a tree.
+            _ if args.sort == ListSort::Name => {
+                for line in formatter.format_tree(&entries) {
+                    output::print("list", line);
+                }
+            }
+            _ => {
+                for (k, v) in entries {
+                    output::print("list", formatter.format_entry(&k, &v));
+                }
             }
         }
-        for (k, v) in effective_map {
-            eprintln!("{} → {:?} (from {:?})", k, v.dest, v.source_file);
+        return;
+    }
+
+    if args.all {
+        let definitions = all_definitions(&layers, &cwd, name, &config.settings);
+        if definitions.is_empty() {
+            // There's nothing to fall back to, so scripts need to be able to tell.
+            fail(&Diagnostic::new("not-found", format!("{:?} isn't defined here", name)), true);
+        }
+        let formatter = list::ListFormatter::new();
+        for entry in definitions {
+            output::print("definition", formatter.format_entry(name, entry));
+        }
+        return;
+    }
+
+    let physical = args.physical
+        || (!args.logical && config.settings.resolve_symlinks.unwrap_or(false));
+    // These are written in Unix shell syntax.
+    let export_vars = args.shell.is_unix()
+        && (args.export || config.settings.export_vars.unwrap_or(false));
+    let record_history = config.settings.record_history.unwrap_or(true);
+    let set_title = args.shell.is_unix() && !args.no_title
+        && (args.title || config.settings.terminal_title.unwrap_or(false));
+    let report_cwd = args.shell.is_unix()
+        && (args.report_cwd || config.settings.report_cwd.unwrap_or(false));
+
+    if args.each {
+        let names = std::iter::once(name).chain(args.extra.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        if let Err(diag) = go_each(&config, &cwd, (&home, &config_path), &names, &args) {
+            fail(&diag, true);
+        }
+        return;
+    }
+    // `entry` is the shortcut used, if any, for how to go to its destination.
+    let go = |dest: &Destination, extra: &str, shortcut: Option<&str>,
+              entry: Option<&PathMappingEntry>| {
+        if entry.is_some_and(|entry| entry.confirm) && !args.yes {
+            let question = format!("go to {}?", shortcut.unwrap_or("it"));
+            match confirm_on_terminal(&question) {
+                Ok(true) => (),
+                Ok(false) => exit(&format!("not going to {}", dest), true),
+                Err(e) => exit(&format!("{} needs confirming, but there's no terminal to ask on \
+                    ({}); pass --yes to go anyway", dest, e), true),
+            }
+        }
+        let cd_cmd = entry.and_then(|entry| entry.cd_cmd.as_deref()).unwrap_or(&args.command);
+        let dest = &prepare_dest(&config, &cwd, (&home, &config_path), dest, extra, entry, &args)
+            .unwrap_or_else(|diag| fail(&diag, true));
+
+        let mut command = dest_command(dest, &args, extra);
+        let mut went_to = dest.to_string();
+        let mut went_to_dir = None;
+        let mut went_to_file = None;
+        if let Destination::Local(path) = dest {
+            let mut full = normalize_lexically(&path.join(extra));
+            command = path_command(&full, cd_cmd, "", args.shell);
+            // A file can't be changed to, so go to its directory instead.
+            let mut file = None;
+            if full.is_file() {
+                if let Some(parent) = full.parent().map(Path::to_owned) {
+                    file = Some(std::mem::replace(&mut full, parent));
+                    command = path_command(&full, cd_cmd, "", args.shell);
+                }
+            }
+            if physical {
+                // If it can't be resolved, it doesn't exist, and the shell will say so.
+                if let Ok(real) = full.canonicalize() {
+                    full = real;
+                    command = path_command(&full, cd_cmd, "", args.shell);
+                }
+            }
+            if args.relative {
+                let relative = relative_path(&full, &cwd);
+                if relative.as_os_str().len() < full.as_os_str().len() {
+                    command = path_command(&relative, cd_cmd, "", args.shell);
+                }
+            }
+            // These have to go on the same line, because the usual wrapper function evaluates our
+            // output unquoted, which joins all the lines together.
+            if export_vars {
+                command += &format!(" && export GOTO_NAME={} GOTO_LAST={}",
+                    shell_quote(shortcut.unwrap_or("")), shell_quote(&full.to_string_lossy()));
+            }
+            if set_title {
+                let title = match shortcut {
+                    Some(name) => name.to_owned(),
+                    None => full.file_name().unwrap_or(full.as_os_str()).to_string_lossy()
+                        .into_owned(),
+                };
+                if let Some(title_cmd) = title_command(&title) {
+                    command += " && ";
+                    command += &title_cmd;
+                }
+            }
+            if report_cwd {
+                if let Some(report_cmd) = report_cwd_command(&full) {
+                    command += " && ";
+                    command += &report_cmd;
+                }
+            }
+            if let (Some(file), true) = (&file, args.open) {
+                command += " && ";
+                command += &args.shell.open_command(&file.to_string_lossy());
+            }
+            went_to = file.as_ref().unwrap_or(&full).display().to_string();
+            went_to_dir = Some(full);
+            went_to_file = file;
+        }
+        if let Some(cd_file) = &args.cd_file {
+            let Some(dir) = &went_to_dir else {
+                exit(&format!("{} isn't on this machine, so it can't be written to {:?}",
+                    dest, cd_file), true);
+            };
+            let contents = match args.cd_file_format {
+                CdFileFormat::Path => dir.to_string_lossy().into_owned(),
+                CdFileFormat::Nnn => format!("cd {}", shell_quote(&dir.to_string_lossy())),
+            };
+            if let Err(e) = std::fs::write(cd_file, contents) {
+                exit(&format!("failed to write {:?}: {}", cd_file, e), true);
+            }
+            if record_history {
+                let _ = history::record_jump(shortcut, &went_to, &cwd);
+            }
+            return;
+        }
+        if args.format == OutputFormat::Json {
+            let kind = match dest {
+                Destination::Local(_) => "local",
+                Destination::Ssh { .. } => "ssh",
+                Destination::Container { .. } => "container",
+                _ => unreachable!("other destinations are resolved before use"),
+            };
+            let opt_path = |path: Option<&Path>| {
+                path.map(|path| path.to_string_lossy().into_owned())
+            };
+            println!("{}", serde_json::json!({
+                "name": shortcut,
+                "kind": kind,
+                "dest": went_to,
+                "dir": opt_path(went_to_dir.as_deref()),
+                "file": opt_path(went_to_file.as_deref()),
+                "extra": extra,
+                "source": opt_path(entry.map(|entry| entry.source_file.as_path())),
+                "context": opt_path(entry.and_then(|entry| entry.context.as_deref())),
+                "command": command,
+            }));
+            return;
+        }
+        if args.print_path {
+            print!("{}{}", went_to, terminator);
+            return;
+        }
+        if args.copy {
+            if let Err(msg) = clipboard::copy(&went_to) {
+                exit(&format!("failed to copy {:?}: {}", went_to, msg), true);
+            }
+            return;
+        }
+        if record_history {
+            // Failing to record it is no reason not to go there.
+            let _ = history::record_jump(shortcut, &went_to, &cwd);
+        }
+        // Nothing will run the command if it goes straight to the terminal: goto was run on its
+        // own, instead of through the shell function.
+        if io::stdout().is_terminal() {
+            match (config.settings.unwrapped.unwrap_or_default(), &went_to_dir) {
+                (Unwrapped::Subshell, Some(dir)) => {
+                    let code = run_subshell(dir).unwrap_or_else(|diag| fail(&diag, true));
+                    ::std::process::exit(code);
+                }
+                _ => output::warn("unwrapped", wrapper_hint(args.shell)),
+            }
+        }
+        println!("{}", command);
+    };
+
+    if let Some(query) = search_to_go {
+        let found = search::search(&config, &cwd, query);
+        if found.is_empty() {
+            exit(&format!("no shortcuts match {:?}", query.join(" ")), true);
+        }
+        let formatter = list::ListFormatter::new();
+        let choices = found.iter()
+            .map(|(name, entry)| formatter.format_entry(name, entry))
+            .collect::<Vec<_>>();
+        let (name, entry) = choose(&choices).map(|i| &found[i])
+            .unwrap_or_else(|msg| exit(&msg, true));
+        go(&entry.dest, extra, Some(name), Some(entry));
+        return;
+    }
+
+    if args.choose {
+        let candidates = all_definitions(&layers, &cwd, name, &config.settings);
+        if candidates.len() > 1 {
+            let choices = candidates.iter()
+                .map(|entry| format!("{} (from {:?})", entry.dest, entry.source_file))
+                .collect::<Vec<_>>();
+            let entry = choose(&choices).map(|i| candidates[i])
+                .unwrap_or_else(|msg| exit(&msg, true));
+            go(&entry.dest, extra, Some(name), Some(entry));
+            return;
+        }
+    }
+
+    if let Some(entry) = timing::time(|| format!("look up {:?}", name),
+        || lookup(&config, &cwd, name))
+    {
+        go(&entry.dest, extra, Some(name), Some(entry));
+        return;
+    }
+
+    // A namespace: "work", where there are shortcuts named like "work.api", means one of them.
+    if !name.is_empty() {
+        let prefix = format!("{}.", name);
+        let children = effective_mapping(&config, &cwd)
+            .into_iter()
+            .filter(|(child, _)| child.starts_with(&prefix))
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            let formatter = list::ListFormatter::new();
+            let choices = children.iter()
+                .map(|(child, entry)| formatter.format_entry(child, entry))
+                .collect::<Vec<_>>();
+            let i = if children.len() == 1 {
+                0
+            } else {
+                choose(&choices).unwrap_or_else(|msg| exit(&msg, true))
+            };
+            let (child, entry) = &children[i];
+            go(&entry.dest, extra, Some(child), Some(entry));
+            return;
+        }
+    }
+
+    // "name/sub/dir" means the shortcut "name", with "sub/dir" added to its path.
+    if let Some((first, rest)) = name.split_once('/') {
+        if let Some(entry) = lookup(&config, &cwd, first) {
+            let extra = if extra.is_empty() {
+                rest.to_owned()
+            } else {
+                join_foreign_path(rest, extra)
+            };
+            go(&entry.dest, &extra, Some(first), Some(entry));
+            return;
         }
-        done = true;
-    } else if !done {
-        if let Some(entry) = config.global.get(&args.name) {
-            print_path(&entry.dest, &args.command, extra);
-            done = true;
+    }
+
+    let path_fallback = !args.no_path_fallback && config.settings.path_fallback.unwrap_or(true);
+    if path_fallback && !name.is_empty() {
+        // Not a shortcut, but maybe it's a directory. (If `name` is absolute, `join` yields it.)
+        let path = cwd.join(name);
+        if path.is_dir() {
+            go(&Destination::Local(path), extra, None, None);
+            return;
+        }
+    }
+
+    if let (Some(roots), false) = (&config.settings.search_roots, name.is_empty()) {
+        let found = search_roots(&config, &cwd, roots, name);
+        let i = if args.choose && found.len() > 1 {
+            let choices = found.iter().map(|path| path.display().to_string()).collect::<Vec<_>>();
+            choose(&choices).unwrap_or_else(|msg| exit(&msg, true))
+        } else {
+            0
+        };
+        if let Some(path) = found.into_iter().nth(i) {
+            go(&Destination::Local(path), extra, None, None);
+            return;
         }
     }
 
-    if !done {
-        exit("not sure where to go", false);
+    match &config.settings.fallback {
+        Some(Fallback::Go(dest)) => {
+            go(dest, extra, None, None);
+            ::std::process::exit(FALLBACK_EXIT_CODE);
+        }
+        Some(Fallback::Error) => fail(&Diagnostic::new("not-found", "not sure where to go"), true),
+        None => fail(&Diagnostic::new("not-found", "not sure where to go"), false),
     }
 }
@@ -0,0 +1,47 @@
+//! Upgrading configuration files to the current format version.
+
+use crate::{config_version, is_entry_table, CONFIG_VERSION, SETTINGS_KEY, VERSION_KEY};
+
+/// Rewrite the contents of a configuration file in the current format version, without changing
+/// what it means. Returns whether anything needed to change.
+pub fn migrate(config_toml: &mut toml::value::Table) -> Result<bool, String> {
+    if let Some(value) = config_toml.get(VERSION_KEY) {
+        if !matches!(value, toml::Value::Integer(_)) {
+            return Err(format!("the shortcut named {:?} must be renamed first", VERSION_KEY));
+        }
+    }
+
+    let version = config_version(config_toml);
+    if version > CONFIG_VERSION {
+        return Err(format!("version {} is newer than this version of goto supports ({})",
+            version, CONFIG_VERSION));
+    }
+    if version == CONFIG_VERSION {
+        return Ok(false);
+    }
+
+    // Version 1 to 2: a top-level table with a "path" string is a context in version 1, but a
+    // shortcut in version 2. Such contexts stay contexts by writing their "path" shortcut as a
+    // table instead.
+    for (key, value) in config_toml.iter_mut() {
+        if key == SETTINGS_KEY {
+            continue;
+        }
+        let toml::Value::Table(table) = value else {
+            continue;
+        };
+        if is_entry_table(table, 1) {
+            continue;
+        }
+        if let Some(path) = table.get_mut("path") {
+            if matches!(path, toml::Value::String(_)) {
+                let mut entry = toml::value::Table::new();
+                entry.insert("path".to_owned(), path.clone());
+                *path = toml::Value::Table(entry);
+            }
+        }
+    }
+
+    config_toml.insert(VERSION_KEY.to_owned(), toml::Value::Integer(CONFIG_VERSION));
+    Ok(true)
+}
@@ -0,0 +1,167 @@
+//! Messages for the user: errors, warnings, and notes about what goto did, all written to stderr,
+//! since stdout is for the shell to run.
+//!
+//! Everything goes through `report`, so that --quiet, --format=json, --message-style, and color
+//! apply the same way to every subcommand.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+use crate::diagnostic::{Diagnostic, Severity};
+
+/// How much of each message to show, set with --message-style.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MessageStyle {
+    /// Only the first line of each message.
+    Terse,
+    /// Whole messages.
+    #[default]
+    Normal,
+    /// Whole messages, followed by what kind of message each is, and where the problem is.
+    Verbose,
+}
+
+/// Set by --quiet: don't print any messages, and signal failure only with the exit code.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set by --format=json: report messages as JSON objects.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// The `MessageStyle`, as its index.
+static STYLE: AtomicU8 = AtomicU8::new(MessageStyle::Normal as u8);
+
+/// Templates for messages, by kind, from the 'messages' setting.
+static TEMPLATES: OnceLock<BTreeMap<String, String>> = OnceLock::new();
+
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+pub fn configure(quiet: bool, json: bool, style: MessageStyle) {
+    QUIET.store(quiet, Ordering::Relaxed);
+    JSON.store(json, Ordering::Relaxed);
+    STYLE.store(style as u8, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Use these templates for messages of the kinds they're keyed by. In a template, `{message}`,
+/// `{kind}`, `{file}`, and `{key}` stand for those parts of the message. Only the first call has
+/// any effect.
+pub fn set_templates(templates: BTreeMap<String, String>) {
+    let _ = TEMPLATES.set(templates);
+}
+
+/// Fill in the template for the message's kind, if there is one.
+fn apply_template(diag: &Diagnostic, message: String) -> String {
+    let Some(template) = TEMPLATES.get().and_then(|templates| templates.get(diag.kind)) else {
+        return message;
+    };
+    let file = diag.file.as_ref().map(|file| file.display().to_string()).unwrap_or_default();
+    template
+        .replace("{kind}", diag.kind)
+        .replace("{file}", &file)
+        .replace("{key}", diag.key.as_deref().unwrap_or_default())
+        .replace("{message}", &message)
+}
+
+fn style() -> MessageStyle {
+    match STYLE.load(Ordering::Relaxed) {
+        s if s == MessageStyle::Terse as u8 => MessageStyle::Terse,
+        s if s == MessageStyle::Verbose as u8 => MessageStyle::Verbose,
+        _ => MessageStyle::Normal,
+    }
+}
+
+/// Whether to use color in what's written to stderr: only if it's a terminal, and the NO_COLOR
+/// environment variable isn't set.
+pub fn use_color() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    !no_color && io::stderr().is_terminal()
+}
+
+/// Write a message to stderr, unless --quiet was given, in the style asked for.
+pub fn report(diag: &Diagnostic) {
+    if is_quiet() {
+        return;
+    }
+    let style = style();
+    let mut msg = if JSON.load(Ordering::Relaxed) {
+        diag.to_json()
+    } else {
+        let mut msg = match style {
+            MessageStyle::Terse => diag.message.lines().next().unwrap_or_default().to_owned(),
+            _ => diag.message.clone(),
+        };
+        msg = apply_template(diag, msg);
+        if style == MessageStyle::Verbose {
+            msg += &format!("\n  ({} {}", diag.severity, diag.kind);
+            if let Some(file) = &diag.file {
+                msg += &format!(", in {:?}", file);
+            }
+            if let Some(key) = &diag.key {
+                msg += &format!(", at {}", key);
+            }
+            msg += ")";
+        }
+        let color = match diag.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => "",
+        };
+        if !color.is_empty() && use_color() {
+            msg = format!("{}{}{}", color, msg, RESET);
+        }
+        msg
+    };
+    if !msg.ends_with('\n') {
+        msg.push('\n');
+    }
+    let _ = io::stderr().write_all(msg.as_bytes());
+}
+
+/// Report a warning: something went wrong, but not badly enough to stop.
+pub fn warn(kind: &'static str, message: impl Into<String>) {
+    report(&Diagnostic::new(kind, message).with_severity(Severity::Warning));
+}
+
+/// Report what was done.
+pub fn note(message: impl Into<String>) {
+    report(&Diagnostic::new("note", message).with_severity(Severity::Note));
+}
+
+/// Write something that was asked for, like one line of a listing, to stderr. Unlike messages,
+/// this isn't hidden by --quiet.
+pub fn print(kind: &'static str, text: impl Into<String>) {
+    let mut text = text.into();
+    if JSON.load(Ordering::Relaxed) {
+        text = Diagnostic::new(kind, text).with_severity(Severity::Note).to_json();
+    }
+    text.push('\n');
+    let _ = io::stderr().write_all(text.as_bytes());
+}
+
+/// Ask the user something on stderr, leaving the cursor after the question for the answer. This
+/// isn't hidden by --quiet, since the question still needs answering.
+pub fn ask(question: &str) {
+    let mut stderr = io::stderr();
+    let _ = write!(stderr, "{} ", question);
+    let _ = stderr.flush();
+}
+
+pub fn exit(msg: &str, fatal: bool) -> ! {
+    fail(&Diagnostic::new("error", msg), fatal)
+}
+
+pub fn fail(diag: &Diagnostic, fatal: bool) -> ! {
+    report(diag);
+    // Without the message, a non-fatal failure is indistinguishable from success unless it gets
+    // an exit code too.
+    let exit_code = if fatal || is_quiet() { 1 } else { 0 };
+    ::std::process::exit(exit_code);
+}
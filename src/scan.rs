@@ -0,0 +1,75 @@
+//! Finding repositories to register as shortcuts.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ignore::Ignore;
+
+/// Names of the directories which mark the root of a repository.
+const REPO_MARKERS: &[&str] = &[".git", ".hg"];
+
+/// Match a name against a simple glob pattern, where '*' matches any run of characters and '?'
+/// matches any single character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position of the last '*' seen, and the position in the name it was matched at, so we can
+    // backtrack and have it consume one more character when a later match fails.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Walk `dir` looking for repositories, down to `depth` levels below it, skipping hidden
+/// directories, those whose names match any of the `ignore` patterns, and those ignored by
+/// `.gotoignore` files. Repositories are not searched for nested repositories. Unreadable
+/// directories are skipped.
+pub fn find_repos(dir: &Path, depth: usize, ignore: &[String]) -> Vec<PathBuf> {
+    let mut repos = vec![];
+    let mut ignore_files = Ignore::for_dir(dir);
+    let mut stack = vec![(dir.to_owned(), 0)];
+    while let Some((dir, level)) = stack.pop() {
+        if level > 0 {
+            ignore_files.add_dir(&dir);
+        }
+        if REPO_MARKERS.iter().any(|marker| dir.join(marker).exists()) {
+            repos.push(dir);
+            continue;
+        }
+        if level == depth {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || ignore.iter().any(|pattern| glob_match(pattern, &name)) {
+                continue;
+            }
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false)
+                && !ignore_files.is_ignored(&entry.path())
+            {
+                stack.push((entry.path(), level + 1));
+            }
+        }
+    }
+    repos.sort();
+    repos
+}
@@ -0,0 +1,64 @@
+//! Searching shortcuts by any part of their names, destinations, tags, and descriptions.
+
+use std::path::Path;
+
+use crate::{effective_mapping, Configuration, PathMappingEntry};
+
+/// How well one word of a query matches a shortcut: the best of its matches against each field,
+/// or None if it doesn't match any of them. Matches are case-insensitive.
+fn word_score(word: &str, name: &str, entry: &PathMappingEntry) -> Option<u32> {
+    let name = name.to_lowercase();
+    let dest = entry.dest.to_string().to_lowercase();
+    let mut scores = vec![];
+
+    if name == word {
+        scores.push(100);
+    } else if name.starts_with(word) {
+        scores.push(60);
+    } else if name.contains(word) {
+        scores.push(40);
+    }
+
+    for tag in &entry.tags {
+        let tag = tag.to_lowercase();
+        if tag == word {
+            scores.push(30);
+        } else if tag.contains(word) {
+            scores.push(15);
+        }
+    }
+
+    if dest.split(['/', '\\']).any(|component| component == word) {
+        scores.push(25);
+    } else if dest.contains(word) {
+        scores.push(15);
+    }
+
+    if entry.desc.as_ref().is_some_and(|desc| desc.to_lowercase().contains(word)) {
+        scores.push(10);
+    }
+
+    scores.into_iter().max()
+}
+
+/// Find the shortcuts in effect in `cwd` which match every word of `query`, best first.
+pub fn search(config: &Configuration, cwd: &Path, query: &[String])
+    -> Vec<(String, PathMappingEntry)>
+{
+    let words = query.iter()
+        .flat_map(|q| q.split_whitespace())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    let mut found = effective_mapping(config, cwd)
+        .into_iter()
+        .filter_map(|(name, entry)| {
+            let score = words.iter()
+                .map(|word| word_score(word, &name, &entry))
+                .sum::<Option<u32>>()?;
+            Some((score, name, entry))
+        })
+        .collect::<Vec<_>>();
+    // Entries come sorted by name already; the sort is stable, so that's the tie-breaker.
+    found.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    found.into_iter().map(|(_, name, entry)| (name, entry)).collect()
+}
@@ -0,0 +1,100 @@
+//! Shell sessions, so that state which belongs to one shell isn't seen from another.
+//!
+//! The shell functions `goto init` makes export the shell's process ID as $GOTO_SESSION; without
+//! it, goto's parent process is taken to be the shell. Each session's state is kept in a directory
+//! of its own, under `sessions` in goto's state directory, named by its ID. Those of sessions which
+//! have ended are removed whenever another session's is created.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{edit, output, state_dir};
+
+/// The environment variable holding the ID of the current shell session.
+pub const SESSION_VAR: &str = "GOTO_SESSION";
+
+const SESSIONS_DIR: &str = "sessions";
+
+/// The ID of the shell session goto is running in, if there's any telling.
+pub fn id() -> Option<String> {
+    match std::env::var(SESSION_VAR) {
+        // It names a directory, so it can't be just anything.
+        Ok(id) if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()) => Some(id),
+        _ => parent_id(),
+    }
+}
+
+#[cfg(unix)]
+fn parent_id() -> Option<String> {
+    Some(std::os::unix::process::parent_id().to_string())
+}
+
+#[cfg(not(unix))]
+fn parent_id() -> Option<String> {
+    None
+}
+
+/// Whether the session with the given ID is still running. Sessions whose IDs aren't process IDs,
+/// or on systems where there's no checking, are assumed to be.
+#[cfg(unix)]
+pub fn is_alive(id: &str) -> bool {
+    let Ok(pid) = id.parse::<libc::pid_t>() else {
+        return true;
+    };
+    if pid <= 0 {
+        return true;
+    }
+    // Signal 0 only checks that the process exists. It fails with EPERM for processes belonging to
+    // someone else, which are still running, so only ESRCH means it's gone.
+    // SAFETY: signal 0 isn't delivered; kill only checks whether it could be.
+    let result = unsafe { libc::kill(pid, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+#[cfg(not(unix))]
+pub fn is_alive(_id: &str) -> bool {
+    true
+}
+
+/// The directory the current session's state is kept in, whether it exists yet or not.
+pub fn dir() -> Option<PathBuf> {
+    Some(state_dir()?.join(SESSIONS_DIR).join(id()?))
+}
+
+/// Create the directory for the current session's state, if it doesn't exist yet, removing those
+/// of sessions which have ended.
+pub fn create_dir() -> Result<PathBuf, String> {
+    let dir = dir().ok_or_else(|| "there's no telling which shell session this is, or no state \
+        directory to keep its state in".to_owned())?;
+    if dir.is_dir() {
+        return Ok(dir);
+    }
+    let sessions = dir.parent().unwrap();
+    fs::create_dir_all(sessions)
+        .map_err(|e| format!("failed to create {:?}: {}", sessions, e))?;
+    let _lock = edit::lock(sessions)
+        .map_err(|e| format!("failed to lock {:?}: {}", sessions, e))?;
+    if let Err(e) = collect_garbage() {
+        output::warn("session",
+            format!("failed to remove the state of ended shell sessions: {}", e));
+    }
+    fs::create_dir_all(&dir)
+        .map_err(|e| format!("failed to create {:?}: {}", dir, e))?;
+    Ok(dir)
+}
+
+/// Remove the state directories of sessions which have ended.
+fn collect_garbage() -> io::Result<()> {
+    let Some(sessions) = state_dir().map(|state| state.join(SESSIONS_DIR)) else {
+        return Ok(());
+    };
+    for entry in fs::read_dir(sessions)? {
+        let entry = entry?;
+        let id = entry.file_name();
+        if entry.file_type()?.is_dir() && !id.to_str().is_some_and(is_alive) {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
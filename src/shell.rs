@@ -0,0 +1,182 @@
+//! The shells goto's output can be written for, and generating the function goto is used through.
+
+use crate::session::SESSION_VAR;
+use crate::shell_quote;
+
+/// Shells that goto can write commands for, and generate a function for.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Sh,
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+    Cmd,
+}
+
+impl Shell {
+    /// The shell's name, as --shell and `goto init` take it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Sh => "sh",
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Powershell => "powershell",
+            Shell::Cmd => "cmd",
+        }
+    }
+
+    /// Whether the shell understands the usual Unix shell syntax for quoting, variables and so
+    /// on, which most of goto's commands are written in.
+    pub fn is_unix(self) -> bool {
+        !matches!(self, Shell::Powershell | Shell::Cmd)
+    }
+
+    /// Quote a string so the shell uses it literally, without any expansion.
+    pub fn quote(self, s: &str) -> String {
+        match self {
+            Shell::Powershell => {
+                // PowerShell also treats the typographic single quotes as quotes.
+                let mut quoted = String::from("'");
+                for c in s.chars() {
+                    if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+                        quoted.push(c);
+                    }
+                    quoted.push(c);
+                }
+                quoted + "'"
+            }
+            // Paths on Windows can't contain double quotes, so there's nothing to escape.
+            Shell::Cmd => format!("\"{}\"", s),
+            _ => shell_quote(s),
+        }
+    }
+
+    /// Make the command which changes to the given directory. `shellcmd` is the command the user
+    /// asked for, which may be empty. The quoted path is added to the end of it, or if it has "{}"
+    /// in it, put in place of that instead.
+    pub fn change_dir_command(self, shellcmd: &str, path: &str) -> String {
+        let mut path = path.to_owned();
+        let mut shellcmd = shellcmd.to_owned();
+        match self {
+            Shell::Powershell if is_unc_path(&path) => {
+                // Naming the provider makes this work even when the current location is in
+                // another one, like the registry.
+                path.insert_str(0, r"Microsoft.PowerShell.Core\FileSystem::");
+            }
+            Shell::Cmd if is_cd(&shellcmd) => {
+                // cd can't go to a UNC path at all, but pushd maps it to a drive letter first.
+                // Otherwise, cd needs /d to go to another drive.
+                shellcmd = if is_unc_path(&path) { "pushd".to_owned() } else { shellcmd + " /d" };
+            }
+            _ => (),
+        }
+
+        if shellcmd.contains("{}") {
+            shellcmd.replace("{}", &self.quote(&path))
+        } else if shellcmd.is_empty() {
+            self.quote(&path)
+        } else {
+            format!("{} {}", shellcmd, self.quote(&path))
+        }
+    }
+
+    /// Make the command which opens the given file for editing: in $EDITOR for Unix shells, or
+    /// with the program associated with it, for Windows shells.
+    pub fn open_command(self, file: &str) -> String {
+        match self {
+            Shell::Powershell => format!("Invoke-Item -LiteralPath {}", self.quote(file)),
+            Shell::Cmd => format!("start \"\" {}", self.quote(file)),
+            // Left unquoted, so it can include arguments, like "code --wait".
+            _ => format!("${{EDITOR:-vi}} {}", self.quote(file)),
+        }
+    }
+}
+
+/// Whether a path is a UNC path, like \\server\share\dir, which Windows shells need special
+/// treatment for.
+fn is_unc_path(path: &str) -> bool {
+    (path.starts_with(r"\\") || path.starts_with("//")) && !path.starts_with(r"\\?\")
+        || path.starts_with(r"\\?\UNC\")
+}
+
+/// Whether a cmd.exe command is its cd builtin.
+fn is_cd(shellcmd: &str) -> bool {
+    shellcmd.eq_ignore_ascii_case("cd") || shellcmd.eq_ignore_ascii_case("chdir")
+}
+
+/// Whether a name can be used for the shell function, in all supported shells.
+pub fn is_valid_function_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Quote a string for fish, where backslashes are special even within single quotes.
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// The definition of a shell function named `name`, which runs the goto program at `binary` with
+/// all its arguments, evaluates the output, and returns goto's exit status. For cmd.exe, which has
+/// no functions, this is a doskey macro instead.
+pub fn shell_function(shell: Shell, name: &str, binary: &str) -> String {
+    match shell {
+        Shell::Sh | Shell::Bash | Shell::Zsh => format!(
+            "{name}() {{\n    local out\n    out=\"$({binary} \"$@\")\"\n    local rc=$?\n    \
+                eval \"$out\"\n    return $rc\n}}\n",
+            binary = shell_quote(binary)),
+        Shell::Fish => format!(
+            "function {name}\n    set -l out ({binary} $argv)\n    set -l rc $status\n    \
+                string join \\n -- $out | source\n    return $rc\nend\n",
+            binary = fish_quote(binary)),
+        Shell::Powershell => format!(
+            "function {name} {{\n    Invoke-Expression (& {binary} --shell powershell @args \
+                | Out-String)\n}}\n",
+            binary = shell.quote(binary)),
+        Shell::Cmd => format!(
+            "doskey {name}=for /f \"delims=\" %i in ('\"{binary}\" --shell cmd $*') do @%i\n"),
+    }
+}
+
+/// A command which exports the shell's process ID as the ID of its session (see `session`), so
+/// that state belonging to one shell isn't seen from another. cmd.exe gets nothing: it has no way
+/// of knowing its own process ID.
+pub fn session_export(shell: Shell) -> String {
+    match shell {
+        Shell::Sh | Shell::Bash | Shell::Zsh => format!("export {}=$$\n", SESSION_VAR),
+        Shell::Fish => format!("set -gx {} $fish_pid\n", SESSION_VAR),
+        Shell::Powershell => format!("$env:{} = $PID\n", SESSION_VAR),
+        Shell::Cmd => String::new(),
+    }
+}
+
+/// A hook which tells the goto program at `binary` about each directory the shell changes to,
+/// with `goto visit`, for `goto init --track`. `name` is the name of the shell function, which
+/// the hook's own function is named after. Shells without a way to run commands on a directory
+/// change get None.
+pub fn visit_hook(shell: Shell, name: &str, binary: &str) -> Option<String> {
+    match shell {
+        Shell::Bash => Some(format!(
+            "__{name}_track() {{\n    \
+                if [ \"$PWD\" != \"$__{name}_last_dir\" ]; then\n        \
+                    __{name}_last_dir=$PWD\n        \
+                    {binary} visit \"$PWD\" 2>/dev/null\n    \
+                fi\n\
+            }}\n\
+            PROMPT_COMMAND=\"__{name}_track${{PROMPT_COMMAND:+; $PROMPT_COMMAND}}\"\n",
+            binary = shell_quote(binary))),
+        Shell::Zsh => Some(format!(
+            "__{name}_track() {{\n    {binary} visit \"$PWD\" 2>/dev/null\n}}\n\
+            autoload -Uz add-zsh-hook\n\
+            add-zsh-hook chpwd __{name}_track\n",
+            binary = shell_quote(binary))),
+        Shell::Fish => Some(format!(
+            "function __{name}_track --on-variable PWD\n    \
+                {binary} visit $PWD 2>/dev/null\n\
+            end\n",
+            binary = fish_quote(binary))),
+        Shell::Sh | Shell::Powershell | Shell::Cmd => None,
+    }
+}
@@ -0,0 +1,154 @@
+//! Temporary shortcuts, added with `goto add --temp`, which are kept in goto's state directory
+//! instead of a configuration file.
+//!
+//! They're kept in `temp` files, one per line, as the time they expire in seconds since the Unix
+//! epoch (0 if they don't), the shell session they belong to (empty if they don't), the name, and
+//! the path, separated by tabs. Shortcuts which expire are kept in the state directory's file, and
+//! those which last for a shell session in the session's own (see `session`), so that they're
+//! only seen from that session, and go away with it. Shortcuts which have expired are dropped
+//! whenever another one is added.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::{edit, history, output, session, state_dir, Configuration, Destination,
+    PathMappingEntry};
+
+const FILE_NAME: &str = "temp";
+
+struct TempShortcut {
+    /// When it expires, in seconds since the Unix epoch, or 0 if it lasts as long as its session.
+    expires: u64,
+    session: String,
+    name: String,
+    path: PathBuf,
+}
+
+impl TempShortcut {
+    /// Whether it can be used, at `now`, from the given session.
+    fn is_current(&self, now: u64, session: Option<&str>) -> bool {
+        (self.expires == 0 || self.expires > now)
+            && (self.session.is_empty() || Some(self.session.as_str()) == session)
+    }
+}
+
+/// Read the temporary shortcuts, including stale ones. Lines which can't be understood are skipped.
+fn read(path: &Path) -> io::Result<Vec<TempShortcut>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut shortcuts = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let fields = line.splitn(4, '\t').collect::<Vec<_>>();
+        let [expires, session, name, path] = fields[..] else {
+            continue;
+        };
+        if let Ok(expires) = expires.parse::<u64>() {
+            shortcuts.push(TempShortcut {
+                expires,
+                session: session.to_owned(),
+                name: name.to_owned(),
+                path: PathBuf::from(path),
+            });
+        }
+    }
+    Ok(shortcuts)
+}
+
+/// Add a temporary shortcut, replacing any other with the same name. It expires after `ttl`
+/// seconds, or without one, when the current shell session ends.
+pub fn add(name: &str, path: &Path, ttl: Option<u64>) -> Result<(), String> {
+    let path_str = path.to_str()
+        .ok_or_else(|| format!("{:?} can't be kept as a temporary shortcut", path))?;
+    if name.contains(['\t', '\n']) || path_str.contains(['\t', '\n']) {
+        return Err(format!("{:?} can't be kept as a temporary shortcut", name));
+    }
+    let now = history::now();
+    let (expires, session, dir) = match ttl {
+        Some(ttl) => {
+            let state = state_dir().ok_or_else(|| {
+                "there's no state directory to keep temporary shortcuts in".to_owned()
+            })?;
+            fs::create_dir_all(&state)
+                .map_err(|e| format!("failed to create {:?}: {}", state, e))?;
+            (now.saturating_add(ttl), String::new(), state)
+        }
+        None => {
+            let dir = session::create_dir()
+                .map_err(|e| format!("shortcuts can't last for the shell session ({}); give a \
+                    --ttl instead", e))?;
+            (0, session::id().unwrap_or_default(), dir)
+        }
+    };
+
+    let file = dir.join(FILE_NAME);
+    let _lock = edit::lock(&file)
+        .map_err(|e| format!("failed to lock {:?}: {}", file, e))?;
+    let mut shortcuts = read(&file)
+        .map_err(|e| format!("failed to read {:?}: {}", file, e))?;
+    shortcuts.retain(|shortcut| {
+        shortcut.name != name
+            && (shortcut.expires == 0 || shortcut.expires > now)
+            && (shortcut.session.is_empty() || shortcut.session == session
+                || session::is_alive(&shortcut.session))
+    });
+    shortcuts.push(TempShortcut {
+        expires,
+        session,
+        name: name.to_owned(),
+        path: path.to_owned(),
+    });
+
+    let text = shortcuts.iter()
+        .map(|shortcut| format!("{}\t{}\t{}\t{}\n", shortcut.expires, shortcut.session,
+            shortcut.name, shortcut.path.display()))
+        .collect::<String>();
+    edit::write_atomically(&file, text.as_bytes())
+        .map_err(|e| format!("failed to write {:?}: {}", file, e))?;
+    match expires {
+        0 => output::note(format!("added {} for this shell session", name)),
+        _ => output::note(format!("added {} until {}", name, history::format_time(expires))),
+    }
+    Ok(())
+}
+
+/// The temporary shortcuts which can be used from here and now, as a configuration layer, if
+/// there are any. If they can't be read, there aren't any.
+pub fn layer() -> Option<Configuration> {
+    let now = history::now();
+    let session = session::id();
+    let files = [state_dir(), session::dir()].into_iter()
+        .flatten()
+        .map(|dir| dir.join(FILE_NAME));
+    let mut config = Configuration::default();
+    for file in files {
+        add_current(&file, now, session.as_deref(), &mut config);
+    }
+    if config.temporary.is_empty() {
+        None
+    } else {
+        Some(config)
+    }
+}
+
+/// Add the temporary shortcuts in `file` which can be used from `session` at `now` to `config`.
+/// If they can't be read, there aren't any.
+fn add_current(file: &Path, now: u64, session: Option<&str>, config: &mut Configuration) {
+    for shortcut in read(file).unwrap_or_default() {
+        if !shortcut.is_current(now, session) {
+            continue;
+        }
+        let desc = match shortcut.expires {
+            0 => "temporary, for this shell session".to_owned(),
+            expires => format!("temporary, until {}", history::format_time(expires)),
+        };
+        config.temporary.insert(shortcut.name, PathMappingEntry {
+            desc: Some(desc),
+            ..PathMappingEntry::new(Destination::Local(shortcut.path), file.to_owned())
+        });
+    }
+}
@@ -0,0 +1,155 @@
+//! Tidying up TOML configuration files, for `goto tidy`.
+//!
+//! The file is rewritten with its keys sorted, tables in order of their names (with settings and
+//! profiles first), keys quoted only where they need to be, strings in double quotes, and paths
+//! under the home directory written with `~/`. Comments stay with the keys and tables they're
+//! attached to.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use toml_edit::{DocumentMut, Formatted, Item, KeyMut, Table, TomlError, Value};
+
+use crate::{backup, config_path_string, daemon, edit, read_config_text, ConfigFormat, HOSTS_KEY,
+    PROFILES_KEY, SETTINGS_KEY};
+
+/// Keys whose string values aren't paths, even where shortcuts are defined.
+const NON_PATH_KEYS: &[&str] = &["desc", "cmd", "container", "shell", "tags", "mount", "cd-cmd"];
+
+/// Quote a key only if it needs to be, keeping any comments before it.
+fn reformat_key(key: &mut KeyMut) {
+    let decor = key.leaf_decor().clone();
+    key.fmt();
+    *key.leaf_decor_mut() = decor;
+}
+
+fn tidy_value(value: &mut Value, key: &str, home: &Path, in_settings: bool) {
+    match value {
+        Value::String(formatted) => {
+            let mut s = formatted.value().clone();
+            if !in_settings && !NON_PATH_KEYS.contains(&key) {
+                s = config_path_string(Path::new(&s), home);
+            }
+            let decor = formatted.decor().clone();
+            *formatted = Formatted::new(s);
+            *formatted.decor_mut() = decor;
+        }
+        Value::Array(array) => {
+            for item in array.iter_mut() {
+                tidy_value(item, key, home, in_settings);
+            }
+        }
+        Value::InlineTable(table) => {
+            table.sort_values();
+            for (mut key, value) in table.iter_mut() {
+                reformat_key(&mut key);
+                let key = key.get().to_owned();
+                tidy_value(value, &key, home, in_settings);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn tidy_table(table: &mut Table, home: &Path, in_settings: bool) {
+    table.sort_values();
+    for (mut key, item) in table.iter_mut() {
+        reformat_key(&mut key);
+        let key = key.get().to_owned();
+        match item {
+            Item::Value(value) => tidy_value(value, &key, home, in_settings),
+            Item::Table(table) => tidy_table(table, home, in_settings || key == SETTINGS_KEY),
+            _ => (),
+        }
+    }
+}
+
+/// Number the tables below `table` in the order they should be written: settings, profiles and
+/// host tables first, then the others by name, each followed by the tables within it.
+fn order_tables(table: &mut Table, next: &mut usize) {
+    let mut keys = table.iter().map(|(key, _)| key.to_owned()).collect::<Vec<_>>();
+    keys.sort_by_key(|key| {
+        (key != SETTINGS_KEY, key != PROFILES_KEY, key != HOSTS_KEY, key.clone())
+    });
+    for key in keys {
+        if let Some(Item::Table(sub)) = table.get_mut(&key) {
+            sub.set_position(*next);
+            *next += 1;
+            order_tables(sub, next);
+        }
+    }
+}
+
+/// Describe the key defined twice, and where, if that's why `text` couldn't be parsed.
+fn duplicate_key(text: &str, error: &TomlError) -> Option<String> {
+    let (key, place) = error.message().strip_prefix("duplicate key `")?.split_once('`')?;
+    let line = error.span()
+        .and_then(|span| text.get(..span.start))
+        .map_or(1, |before| before.matches('\n').count() + 1);
+    Some(format!("{} is defined again on line {}{}", key, line, place))
+}
+
+/// Find the shortcuts defined in the same table with the same destination, and describe them.
+fn find_same_destinations(table: &Table, table_name: &str, found: &mut Vec<String>) {
+    let mut by_dest = BTreeMap::<String, Vec<&str>>::new();
+    for (key, item) in table.iter() {
+        match item {
+            Item::Value(Value::String(dest)) => {
+                by_dest.entry(dest.value().clone()).or_default().push(key);
+            }
+            Item::Table(sub) if key != SETTINGS_KEY => {
+                let sub_name = if table_name.is_empty() {
+                    key.to_owned()
+                } else {
+                    format!("{}.{}", table_name, key)
+                };
+                find_same_destinations(sub, &sub_name, found);
+            }
+            _ => (),
+        }
+    }
+    for (dest, names) in by_dest {
+        if names.len() > 1 {
+            let place = if table_name.is_empty() {
+                String::new()
+            } else {
+                format!(" in [{}]", table_name)
+            };
+            found.push(format!("{}{} all lead to {:?}", names.join(", "), place, dest));
+        }
+    }
+}
+
+/// Tidy up a TOML configuration file. With `dry_run`, it's only checked. Returns whether it
+/// needed tidying, along with descriptions of shortcuts which lead to the same place, which are
+/// left for the user to deal with. A file with a key defined twice can't be tidied; which key it
+/// is, and where, is in the error.
+pub fn tidy(path: &Path, home: &Path, dry_run: bool) -> Result<(bool, Vec<String>), String> {
+    if ConfigFormat::of(path) != ConfigFormat::Toml {
+        return Err(format!("{:?} isn't a TOML file; only those can be tidied", path));
+    }
+    let _lock = edit::lock(path)
+        .map_err(|e| format!("failed to lock configuration {:?}: {}", path, e))?;
+    let text = read_config_text(path)
+        .map_err(|e| format!("failed to read configuration {:?}: {}", path, e))?;
+    let mut doc = text.parse::<DocumentMut>().map_err(|e| match duplicate_key(&text, &e) {
+        Some(duplicate) => format!("{:?} can't be tidied: {}", path, duplicate),
+        None => format!("failed to parse configuration {:?}: {}", path, e),
+    })?;
+
+    let root = doc.as_table_mut();
+    tidy_table(root, home, false);
+    order_tables(root, &mut 0);
+    let mut same_destinations = vec![];
+    find_same_destinations(root, "", &mut same_destinations);
+
+    let tidied = doc.to_string();
+    let changed = tidied != text;
+    if changed && !dry_run {
+        let write_error = |e| format!("failed to write configuration {:?}: {}", path, e);
+        backup::save(path, Some(&text)).map_err(write_error)?;
+        edit::write_atomically(path, tidied.as_bytes()).map_err(write_error)?;
+        daemon::invalidate();
+    }
+    Ok((changed, same_destinations))
+}
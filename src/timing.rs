@@ -0,0 +1,40 @@
+//! How long each phase of starting up takes, for --profile-startup: finding and reading the
+//! configuration files, parsing them, merging them, and resolving where to go.
+//!
+//! Each phase is reported on stderr as it finishes, with when it started, counted from when timing
+//! was enabled, since files are found and read in several threads at once. Phases which never
+//! finish, like reading from a filesystem which has stopped responding, are never reported.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static START: OnceLock<Instant> = OnceLock::new();
+
+/// Start timing phases.
+pub fn enable() {
+    START.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f`, and if timing is enabled, report how long it took as the phase `phase` describes.
+pub fn time<T>(phase: impl FnOnce() -> String, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let started = Instant::now();
+    let result = f();
+    report(&phase(), started, started.elapsed());
+    result
+}
+
+fn report(phase: &str, started: Instant, took: Duration) {
+    let offset = START.get().map(|start| started.saturating_duration_since(*start))
+        .unwrap_or_default();
+    eprintln!("startup: {:>9.3} ms  {} (from {:.3} ms)", millis(took), phase, millis(offset));
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
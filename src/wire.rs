@@ -0,0 +1,295 @@
+//! Configurations written as JSON, for the daemon to send them to goto already parsed.
+//!
+//! Paths are written as strings where they're valid Unicode, and otherwise as arrays of bytes.
+//! Reading gives None for anything that isn't as written here, and goto then reads the files
+//! itself.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::{
+    Configuration, Destination, Fallback, NameCase, PathMapping, PathMappingEntry, SearchRoot,
+    Settings, Unwrapped,
+};
+
+fn path(path: &Path) -> Value {
+    match path.to_str() {
+        Some(s) => Value::from(s),
+        None => Value::from(path.as_os_str().as_bytes()),
+    }
+}
+
+fn read_path(value: &Value) -> Option<PathBuf> {
+    match value {
+        Value::String(s) => Some(PathBuf::from(s)),
+        Value::Array(bytes) => bytes.iter()
+            .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .map(|bytes| PathBuf::from(OsString::from_vec(bytes))),
+        _ => None,
+    }
+}
+
+/// Read an optional value, which is null if it's absent: None if it isn't valid, otherwise
+/// Some(None) if it's absent.
+fn read_opt<T>(value: &Value, read: impl Fn(&Value) -> Option<T>) -> Option<Option<T>> {
+    match value {
+        Value::Null => Some(None),
+        value => read(value).map(Some),
+    }
+}
+
+fn read_string(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_owned)
+}
+
+fn read_strings(value: &Value) -> Option<Vec<String>> {
+    value.as_array()?.iter().map(read_string).collect()
+}
+
+fn dest(dest: &Destination) -> Value {
+    match dest {
+        Destination::Local(dir) => json!({ "local": path(dir) }),
+        Destination::Ssh { host, path } => json!({ "ssh": { "host": host, "path": path } }),
+        Destination::Container { name, path, shell } => {
+            json!({ "container": { "name": name, "path": path, "shell": shell } })
+        }
+        Destination::Command { cmd, dir } => json!({ "command": { "cmd": cmd, "dir": path(dir) } }),
+        Destination::Alias(name) => json!({ "alias": name }),
+        Destination::Alternatives(alternatives) => {
+            json!({ "alternatives": alternatives.iter().map(self::dest).collect::<Vec<_>>() })
+        }
+        Destination::NewestChild(inner) => json!({ "newest-child": self::dest(inner) }),
+        Destination::LastVisited { under, fallback } => {
+            json!({ "last-visited": { "under": path(under), "fallback": self::dest(fallback) } })
+        }
+        Destination::NearestRoot { from, markers } => {
+            json!({ "nearest-root": { "from": path(from), "markers": markers } })
+        }
+        Destination::Disabled => json!("disabled"),
+    }
+}
+
+fn read_dest(value: &Value) -> Option<Destination> {
+    if value == "disabled" {
+        return Some(Destination::Disabled);
+    }
+    let (kind, v) = value.as_object()?.iter().next()?;
+    Some(match kind.as_str() {
+        "local" => Destination::Local(read_path(v)?),
+        "ssh" => Destination::Ssh {
+            host: read_string(&v["host"])?,
+            path: read_string(&v["path"])?,
+        },
+        "container" => Destination::Container {
+            name: read_string(&v["name"])?,
+            path: read_string(&v["path"])?,
+            shell: read_string(&v["shell"])?,
+        },
+        "command" => Destination::Command {
+            cmd: read_string(&v["cmd"])?,
+            dir: read_path(&v["dir"])?,
+        },
+        "alias" => Destination::Alias(read_string(v)?),
+        "alternatives" => {
+            Destination::Alternatives(v.as_array()?.iter().map(read_dest).collect::<Option<_>>()?)
+        }
+        "newest-child" => Destination::NewestChild(Box::new(read_dest(v)?)),
+        "last-visited" => Destination::LastVisited {
+            under: read_path(&v["under"])?,
+            fallback: Box::new(read_dest(&v["fallback"])?),
+        },
+        "nearest-root" => Destination::NearestRoot {
+            from: read_path(&v["from"])?,
+            markers: read_strings(&v["markers"])?,
+        },
+        _ => return None,
+    })
+}
+
+fn entry(entry: &PathMappingEntry) -> Value {
+    json!({
+        "dest": dest(&entry.dest),
+        "source": path(&entry.source_file),
+        "context": entry.context.as_deref().map(path),
+        "desc": entry.desc,
+        "tags": entry.tags,
+        "priority": entry.priority,
+        "create": entry.create,
+        "confirm": entry.confirm,
+        "mount": entry.mount,
+        "cd-cmd": entry.cd_cmd,
+    })
+}
+
+fn read_entry(value: &Value) -> Option<PathMappingEntry> {
+    Some(PathMappingEntry {
+        dest: read_dest(&value["dest"])?,
+        source_file: read_path(&value["source"])?,
+        context: read_opt(&value["context"], read_path)?,
+        desc: read_opt(&value["desc"], read_string)?,
+        tags: read_strings(&value["tags"])?,
+        priority: value["priority"].as_i64()?,
+        create: value["create"].as_bool()?,
+        confirm: value["confirm"].as_bool()?,
+        mount: read_opt(&value["mount"], read_string)?,
+        cd_cmd: read_opt(&value["cd-cmd"], read_string)?,
+    })
+}
+
+fn mapping(mapping: &PathMapping) -> Value {
+    Value::Object(mapping.iter().map(|(name, e)| (name.clone(), entry(e))).collect())
+}
+
+fn read_mapping(value: &Value) -> Option<PathMapping> {
+    value.as_object()?.iter()
+        .map(|(name, e)| Some((name.clone(), read_entry(e)?)))
+        .collect()
+}
+
+fn settings(settings: &Settings) -> Value {
+    json!({
+        "path-fallback": settings.path_fallback,
+        "search-roots": settings.search_roots.as_ref().map(|roots| roots.iter()
+            .map(|root| json!({ "name": root.name, "weight": root.weight }))
+            .collect::<Vec<_>>()),
+        "verify-extra": settings.verify_extra,
+        "export-vars": settings.export_vars,
+        "terminal-title": settings.terminal_title,
+        "report-cwd": settings.report_cwd,
+        "name-case": settings.name_case.map(|name_case| match name_case {
+            NameCase::Sensitive => "sensitive",
+            NameCase::Insensitive => "insensitive",
+            NameCase::Smart => "smart",
+        }),
+        "resolve-symlinks": settings.resolve_symlinks,
+        "normalize-unicode": settings.normalize_unicode,
+        "resolve-contexts": settings.resolve_contexts,
+        "home-lowest": settings.home_lowest,
+        "fallback": settings.fallback.as_ref().map(|fallback| match fallback {
+            Fallback::Error => json!("error"),
+            Fallback::Go(to) => dest(to),
+        }),
+        "record-history": settings.record_history,
+        "overlays": settings.overlays.as_ref()
+            .map(|overlays| overlays.iter().map(|p| path(p)).collect::<Vec<_>>()),
+        "root-markers": settings.root_markers,
+        "unwrapped": settings.unwrapped.map(|unwrapped| match unwrapped {
+            Unwrapped::Hint => "hint",
+            Unwrapped::Subshell => "subshell",
+        }),
+        "stale-after": settings.stale_after,
+        "messages": settings.messages,
+    })
+}
+
+fn read_settings(value: &Value) -> Option<Settings> {
+    let bool = |key: &str| read_opt(&value[key], Value::as_bool);
+    Some(Settings {
+        path_fallback: bool("path-fallback")?,
+        search_roots: read_opt(&value["search-roots"], |roots| {
+            roots.as_array()?.iter()
+                .map(|root| Some(SearchRoot {
+                    name: read_string(&root["name"])?,
+                    weight: root["weight"].as_f64()?,
+                }))
+                .collect()
+        })?,
+        verify_extra: bool("verify-extra")?,
+        export_vars: bool("export-vars")?,
+        terminal_title: bool("terminal-title")?,
+        report_cwd: bool("report-cwd")?,
+        name_case: read_opt(&value["name-case"], |name_case| match name_case.as_str()? {
+            "sensitive" => Some(NameCase::Sensitive),
+            "insensitive" => Some(NameCase::Insensitive),
+            "smart" => Some(NameCase::Smart),
+            _ => None,
+        })?,
+        resolve_symlinks: bool("resolve-symlinks")?,
+        normalize_unicode: bool("normalize-unicode")?,
+        resolve_contexts: bool("resolve-contexts")?,
+        home_lowest: bool("home-lowest")?,
+        fallback: read_opt(&value["fallback"], |fallback| match fallback.as_str() {
+            Some("error") => Some(Fallback::Error),
+            _ => read_dest(fallback).map(Fallback::Go),
+        })?,
+        record_history: bool("record-history")?,
+        overlays: read_opt(&value["overlays"], |overlays| {
+            overlays.as_array()?.iter().map(read_path).collect()
+        })?,
+        root_markers: read_opt(&value["root-markers"], read_strings)?,
+        unwrapped: read_opt(&value["unwrapped"], |unwrapped| match unwrapped.as_str()? {
+            "hint" => Some(Unwrapped::Hint),
+            "subshell" => Some(Unwrapped::Subshell),
+            _ => None,
+        })?,
+        stale_after: read_opt(&value["stale-after"], Value::as_u64)?,
+        messages: read_opt(&value["messages"], |messages| {
+            messages.as_object()?.iter()
+                .map(|(kind, template)| Some((kind.clone(), read_string(template)?)))
+                .collect::<Option<BTreeMap<_, _>>>()
+        })?,
+    })
+}
+
+pub fn config(config: &Configuration) -> Value {
+    json!({
+        "global": mapping(&config.global),
+        "contexts": config.contexts.iter()
+            .map(|(context, m)| json!([path(context), mapping(m)]))
+            .collect::<Vec<_>>(),
+        "temporary": mapping(&config.temporary),
+        "settings": settings(&config.settings),
+        "host": config.host_overrides.as_deref().map(self::config),
+        "workspaces": config.workspaces,
+    })
+}
+
+pub fn read_config(value: &Value) -> Option<Configuration> {
+    Some(Configuration {
+        global: read_mapping(&value["global"])?,
+        contexts: value["contexts"].as_array()?.iter()
+            .map(|context| Some((read_path(&context[0])?, read_mapping(&context[1])?)))
+            .collect::<Option<_>>()?,
+        temporary: read_mapping(&value["temporary"])?,
+        settings: read_settings(&value["settings"])?,
+        host_overrides: read_opt(&value["host"], |host| read_config(host).map(Box::new))?,
+        workspaces: value["workspaces"].as_object()?.iter()
+            .map(|(name, names)| Some((name.clone(), read_strings(names)?)))
+            .collect::<Option<_>>()?,
+    })
+}
+
+pub fn diagnostic(diag: &Diagnostic) -> Value {
+    json!({
+        "severity": diag.severity.to_string(),
+        "kind": diag.kind,
+        "message": diag.message,
+        "file": diag.file.as_deref().map(path),
+        "key": diag.key,
+    })
+}
+
+pub fn read_diagnostic(value: &Value) -> Option<Diagnostic> {
+    let severity = match value["severity"].as_str()? {
+        "error" => Severity::Error,
+        "warning" => Severity::Warning,
+        "note" => Severity::Note,
+        _ => return None,
+    };
+    // Kinds are meant to be written into goto itself. The few which come from the daemon are kept
+    // for as long as goto runs, which isn't long.
+    let kind: &'static str = Box::leak(read_string(&value["kind"])?.into_boxed_str());
+    Some(Diagnostic {
+        severity,
+        kind,
+        message: read_string(&value["message"])?,
+        file: read_opt(&value["file"], read_path)?,
+        key: read_opt(&value["key"], read_string)?,
+    })
+}